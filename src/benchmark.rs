@@ -0,0 +1,147 @@
+// Benchmark mode, started with `--benchmark`. It stamps a dungeon from a fixed
+// seed, sweeps the camera around it on a fixed path and times how long every
+// frame takes to draw and post-process. Presenting isn't timed since it mostly
+// waits for vsync. The world steps one tick per frame with no input and only
+// the built-in themes, so runs don't depend on frame rate, keys or the user's
+// files. Once the path is done a summary gets written to
+// benchmark-<timestamp>.txt, so two builds can be compared run against run.
+
+use std::f32::consts::TAU;
+use std::fs;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::generate::{self, Layout};
+use crate::rng::Rng;
+
+const SEED: u64 = 0x5eed;
+const DUNGEON_SIZE: (i32, i32) = (48, 36);
+pub const BIRDS: usize = 200;
+const FRAMES: usize = 600;
+
+// The same dungeon every run
+pub fn dungeon() -> Layout {
+    generate::dungeon(DUNGEON_SIZE.0, DUNGEON_SIZE.1, &mut Rng::new(SEED))
+}
+
+// Frame time statistics in milliseconds
+struct Summary {
+    mean: f64,
+    median: f64,
+    p95: f64,
+    p99: f64,
+    worst: f64,
+}
+
+pub struct Benchmark {
+    // Center and radii of the elliptical path in world pixels
+    center: (f32, f32),
+    radius: (f32, f32),
+    timings: Vec<Duration>,
+}
+
+impl Benchmark {
+
+    pub fn new(center: (f32, f32), radius: (f32, f32)) -> Self {
+        Self { center, radius, timings: Vec::with_capacity(FRAMES) }
+    }
+
+    // Where the camera should be for the next frame. The path goes by frame
+    // count rather than time so every run draws exactly the same frames.
+    pub fn camera_pos(&self) -> (f32, f32) {
+        let angle = self.timings.len() as f32 / FRAMES as f32 * TAU;
+        (self.center.0 + self.radius.0 * angle.cos(), self.center.1 + self.radius.1 * angle.sin())
+    }
+
+    // Records the time one frame took, returns true once the path is done
+    pub fn record(&mut self, frame_time: Duration) -> bool {
+        self.timings.push(frame_time);
+        self.timings.len() >= FRAMES
+    }
+
+    fn summary(&self) -> Summary {
+        let mut sorted = self.timings.clone();
+        sorted.sort();
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        let percentile = |p: f64| ms(sorted[((sorted.len() - 1) as f64 * p).round() as usize]);
+        Summary {
+            mean: ms(sorted.iter().sum::<Duration>()) / sorted.len() as f64,
+            median: percentile(0.5),
+            p95: percentile(0.95),
+            p99: percentile(0.99),
+            worst: ms(sorted[sorted.len() - 1]),
+        }
+    }
+
+    pub fn finish(&self) {
+        let summary = self.summary();
+        let report = format!(
+            "{} {} benchmark\n\
+             frames: {}\n\
+             seed: {:#x}\n\
+             mean: {:.3} ms\n\
+             median: {:.3} ms\n\
+             p95: {:.3} ms\n\
+             p99: {:.3} ms\n\
+             worst: {:.3} ms\n",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION"),
+            self.timings.len(),
+            SEED,
+            summary.mean,
+            summary.median,
+            summary.p95,
+            summary.p99,
+            summary.worst,
+        );
+        print!("{}", report);
+
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let path = format!("benchmark-{}.txt", timestamp);
+        if let Err(err) = fs::write(&path, report) {
+            eprintln!("Couldn't write benchmark report {}: {}", path, err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: (f32, f32), b: (f32, f32)) -> bool {
+        (a.0 - b.0).abs() < 1e-3 && (a.1 - b.1).abs() < 1e-3
+    }
+
+    #[test]
+    fn summary_of_frame_times() {
+        let mut benchmark = Benchmark::new((0.0, 0.0), (1.0, 1.0));
+        // 1 to 100 ms, recorded out of order
+        for i in 0..100 {
+            benchmark.record(Duration::from_millis((i * 37 % 100) + 1));
+        }
+        let summary = benchmark.summary();
+        assert!((summary.mean - 50.5).abs() < 1e-9);
+        assert_eq!(summary.median, 51.0);
+        assert_eq!(summary.p95, 95.0);
+        assert_eq!(summary.p99, 99.0);
+        assert_eq!(summary.worst, 100.0);
+    }
+
+    #[test]
+    fn camera_goes_once_around_the_ellipse() {
+        let mut benchmark = Benchmark::new((100.0, 50.0), (40.0, 20.0));
+        assert!(close(benchmark.camera_pos(), (140.0, 50.0)));
+
+        let mut positions = Vec::new();
+        for frame in 1..=FRAMES {
+            positions.push(benchmark.camera_pos());
+            // The path only depends on the frame count, not how long frames took
+            let done = benchmark.record(Duration::from_micros(frame as u64 * 7 % 5000));
+            assert_eq!(done, frame == FRAMES);
+        }
+        assert!(close(positions[FRAMES / 4], (100.0, 70.0)));
+        assert!(close(positions[FRAMES / 2], (60.0, 50.0)));
+        assert!(close(positions[FRAMES * 3 / 4], (100.0, 30.0)));
+        // Back where it started once the path is done
+        assert!(close(benchmark.camera_pos(), (140.0, 50.0)));
+    }
+}
@@ -0,0 +1,75 @@
+// Camera for the top-down view. It keeps track of which world position sits in
+// the middle of the screen and eases towards the player instead of snapping.
+
+pub const LOOK_AHEAD: f32 = 0.25;
+
+pub struct Camera {
+    pub pos: (f32, f32),
+    vel: (f32, f32),
+    // Roughly the time in seconds the camera needs to catch up with its target
+    pub smooth_time: f32,
+    // Fraction of the player -> cursor vector the camera leans towards
+    pub look_ahead: Option<f32>,
+    half_screen: (f32, f32),
+}
+
+impl Camera {
+
+    pub fn new(pos: (f32, f32), screen_width: u32, screen_height: u32) -> Self {
+        Self {
+            pos,
+            vel: (0.0, 0.0),
+            smooth_time: 0.25,
+            look_ahead: Some(LOOK_AHEAD),
+            half_screen: (screen_width as f32 / 2.0, screen_height as f32 / 2.0),
+        }
+    }
+
+    // Call when the area the camera draws into changes size
+    pub fn resize(&mut self, screen_width: u32, screen_height: u32) {
+        self.half_screen = (screen_width as f32 / 2.0, screen_height as f32 / 2.0);
+    }
+
+    // Move the camera towards the player, optionally leaning towards the cursor
+    // which is given in world coordinates
+    pub fn follow(&mut self, player: (f32, f32), cursor: Option<(f32, f32)>, dt: f32) {
+        let mut target = player;
+        if let (Some(amount), Some(cursor)) = (self.look_ahead, cursor) {
+            target.0 += (cursor.0 - player.0) * amount;
+            target.1 += (cursor.1 - player.1) * amount;
+        }
+
+        let (x, vx) = smooth_damp(self.pos.0, target.0, self.vel.0, self.smooth_time, dt);
+        let (y, vy) = smooth_damp(self.pos.1, target.1, self.vel.1, self.smooth_time, dt);
+        self.pos = (x, y);
+        self.vel = (vx, vy);
+    }
+
+    // World position that ends up at the top left corner of the screen
+    pub fn origin(&self) -> (f32, f32) {
+        (self.pos.0 - self.half_screen.0, self.pos.1 - self.half_screen.1)
+    }
+
+    pub fn world_to_screen(&self, p: (f32, f32)) -> (f32, f32) {
+        let origin = self.origin();
+        (p.0 - origin.0, p.1 - origin.1)
+    }
+
+    pub fn screen_to_world(&self, p: (f32, f32)) -> (f32, f32) {
+        let origin = self.origin();
+        (p.0 + origin.0, p.1 + origin.1)
+    }
+}
+
+// Critically damped spring, reaches the target as fast as possible without
+// overshooting. Uses the polynomial approximation of exp() from Game
+// Programming Gems 4 so it stays stable for large time steps.
+fn smooth_damp(current: f32, target: f32, velocity: f32, smooth_time: f32, dt: f32) -> (f32, f32) {
+    let omega = 2.0 / smooth_time.max(0.0001);
+    let x = omega * dt;
+    let decay = 1.0 / (1.0 + x + 0.48 * x * x + 0.235 * x * x * x);
+    let change = current - target;
+    let temp = (velocity + omega * change) * dt;
+    let velocity = (velocity - omega * temp) * decay;
+    (target + (change + temp) * decay, velocity)
+}
@@ -0,0 +1,146 @@
+// Camera paths for photo mode. Keyframes are camera positions dropped while
+// moving around, and playback glides through all of them along a Catmull-Rom
+// spline. The spline gets measured when playback starts so the camera can
+// move at a constant speed instead of rushing between keyframes that are far
+// apart and crawling between close ones.
+
+const SPEED: f32 = 200.0; // World pixels per second
+const SAMPLES_PER_SEGMENT: usize = 32;
+
+pub struct CameraPath {
+    keyframes: Vec<(f32, f32)>,
+    // Points along the spline with the distance travelled to reach them
+    samples: Vec<(f32, (f32, f32))>,
+    // Distance covered so far while playing
+    playing: Option<f32>,
+}
+
+impl CameraPath {
+
+    pub fn new() -> Self {
+        Self { keyframes: Vec::new(), samples: Vec::new(), playing: None }
+    }
+
+    pub fn len(&self) -> usize {
+        self.keyframes.len()
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing.is_some()
+    }
+
+    pub fn add(&mut self, pos: (f32, f32)) {
+        self.keyframes.push(pos);
+    }
+
+    pub fn clear(&mut self) {
+        self.keyframes.clear();
+        self.playing = None;
+    }
+
+    // Starts playing from the first keyframe, returns false if there aren't
+    // enough keyframes to make a path
+    pub fn play(&mut self) -> bool {
+        if self.keyframes.len() < 2 {
+            return false;
+        }
+
+        // The spline needs a point before and after each segment, so the
+        // ends get repeated
+        let mut points = vec![self.keyframes[0]];
+        points.extend(&self.keyframes);
+        points.push(self.keyframes[self.keyframes.len() - 1]);
+
+        self.samples.clear();
+        let mut distance = 0.0;
+        let mut last = self.keyframes[0];
+        for window in points.windows(4) {
+            for i in 0..SAMPLES_PER_SEGMENT {
+                let t = i as f32 / SAMPLES_PER_SEGMENT as f32;
+                let point = catmull_rom(window[0], window[1], window[2], window[3], t);
+                distance += (point.0 - last.0).hypot(point.1 - last.1);
+                self.samples.push((distance, point));
+                last = point;
+            }
+        }
+        let end = self.keyframes[self.keyframes.len() - 1];
+        distance += (end.0 - last.0).hypot(end.1 - last.1);
+        self.samples.push((distance, end));
+
+        self.playing = Some(0.0);
+        true
+    }
+
+    pub fn stop(&mut self) {
+        self.playing = None;
+    }
+
+    // Moves playback along by `dt` seconds and returns where the camera goes,
+    // or None if the path isn't playing or just finished
+    pub fn advance(&mut self, dt: f32) -> Option<(f32, f32)> {
+        let distance = self.playing? + SPEED * dt;
+        let total = self.samples.last()?.0;
+        if distance > total {
+            self.playing = None;
+            return None;
+        }
+        self.playing = Some(distance);
+
+        // Find the two samples around this distance and blend between them
+        let next = self.samples.partition_point(|&(d, _)| d < distance).max(1);
+        let (d0, a) = self.samples[next - 1];
+        let (d1, b) = self.samples[next];
+        let t = if d1 > d0 { (distance - d0) / (d1 - d0) } else { 0.0 };
+        Some((a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t))
+    }
+}
+
+// Point `t` of the way from `p1` to `p2` on a Catmull-Rom spline
+fn catmull_rom(p0: (f32, f32), p1: (f32, f32), p2: (f32, f32), p3: (f32, f32), t: f32) -> (f32, f32) {
+    let (t2, t3) = (t * t, t * t * t);
+    let blend = |a: f32, b: f32, c: f32, d: f32| {
+        0.5 * (2.0 * b + (c - a) * t + (2.0 * a - 5.0 * b + 4.0 * c - d) * t2 + (3.0 * b - a - 3.0 * c + d) * t3)
+    };
+    (blend(p0.0, p1.0, p2.0, p3.0), blend(p0.1, p1.1, p2.1, p3.1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn needs_two_keyframes_to_play() {
+        let mut path = CameraPath::new();
+        assert!(!path.play());
+        path.add((0.0, 0.0));
+        assert!(!path.play());
+        path.add((100.0, 0.0));
+        assert!(path.play());
+        assert!(path.is_playing());
+    }
+
+    #[test]
+    fn moves_at_constant_speed() {
+        // Keyframes close together and far apart, on a gentle curve so the
+        // distance between frames matches the distance along the path
+        let mut path = CameraPath::new();
+        for pos in [(0.0, 0.0), (60.0, 0.0), (300.0, 40.0), (360.0, 200.0), (0.0, 320.0)] {
+            path.add(pos);
+        }
+        assert!(path.play());
+
+        let dt = 1.0 / 60.0;
+        let mut last = (0.0, 0.0);
+        let mut steps = 0;
+        while let Some(pos) = path.advance(dt) {
+            let step = (pos.0 - last.0).hypot(pos.1 - last.1);
+            assert!((step - SPEED * dt).abs() < SPEED * dt * 0.05, "step {} moved {}", steps, step);
+            last = pos;
+            steps += 1;
+        }
+        assert!(!path.is_playing());
+        // Finished close to the last keyframe
+        assert!(last.0.hypot(last.1 - 320.0) <= SPEED * dt);
+        assert!(steps > 100);
+    }
+}
@@ -0,0 +1,119 @@
+// Fixed timestep clock for the simulation. Rendering happens once per frame
+// while the world is advanced in ticks of constant length, so slowing down,
+// speeding up or pausing time only changes how many ticks run each frame.
+
+use winit::keyboard::KeyCode;
+use winit_input_helper::WinitInputHelper;
+
+pub const TICK_RATE: f32 = 60.0;
+pub const TICK_DT: f32 = 1.0 / TICK_RATE;
+
+// Upper bound so a slow frame (or a big timescale) can't snowball into running
+// more and more ticks every frame
+const MAX_TICKS_PER_FRAME: u32 = 16;
+
+const MIN_TIMESCALE: f32 = 0.125;
+const MAX_TIMESCALE: f32 = 8.0;
+
+pub struct Clock {
+    pub timescale: f32,
+    pub paused: bool,
+    accumulator: f32,
+    step: bool,
+}
+
+impl Clock {
+
+    pub fn new() -> Self {
+        Self { timescale: 1.0, paused: false, accumulator: 0.0, step: false }
+    }
+
+    // , and . halve or double the timescale, / resets it, Space pauses and
+    // Tab advances a single tick while paused
+    pub fn handle_input(&mut self, input: &WinitInputHelper) {
+        if input.key_pressed(KeyCode::Comma) {
+            self.timescale = (self.timescale / 2.0).max(MIN_TIMESCALE);
+        }
+        if input.key_pressed(KeyCode::Period) {
+            self.timescale = (self.timescale * 2.0).min(MAX_TIMESCALE);
+        }
+        if input.key_pressed(KeyCode::Slash) {
+            self.timescale = 1.0;
+        }
+        if input.key_pressed(KeyCode::Space) {
+            self.paused = !self.paused;
+            self.accumulator = 0.0;
+        }
+        if input.key_pressed(KeyCode::Tab) && self.paused {
+            self.step = true;
+        }
+    }
+
+    // Number of ticks to run for a frame that took `frame_dt` seconds
+    pub fn advance(&mut self, frame_dt: f32) -> u32 {
+        if self.paused {
+            let ticks = self.step as u32;
+            self.step = false;
+            return ticks;
+        }
+
+        self.accumulator += frame_dt * self.timescale;
+        let ticks = (self.accumulator / TICK_DT) as u32;
+        self.accumulator -= ticks as f32 * TICK_DT;
+
+        if ticks > MAX_TICKS_PER_FRAME {
+            self.accumulator = 0.0;
+            return MAX_TICKS_PER_FRAME;
+        }
+        ticks
+    }
+
+    // Short description for the window title, empty while running normally
+    pub fn status(&self) -> String {
+        if self.paused {
+            "paused".to_string()
+        } else if self.timescale != 1.0 {
+            format!("{}x", self.timescale)
+        } else {
+            String::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_a_tick_for_every_tick_of_frame_time() {
+        let mut clock = Clock::new();
+        assert_eq!(clock.advance(TICK_DT * 3.0), 3);
+        // Partial ticks carry over to the next frame
+        assert_eq!(clock.advance(TICK_DT * 0.6), 0);
+        assert_eq!(clock.advance(TICK_DT * 0.6), 1);
+        assert_eq!(clock.advance(TICK_DT * 1.0), 1);
+
+        clock.timescale = 2.0;
+        assert_eq!(clock.advance(TICK_DT * 2.0), 4);
+    }
+
+    #[test]
+    fn slow_frames_drop_the_backlog() {
+        let mut clock = Clock::new();
+        assert_eq!(clock.advance(1.0), MAX_TICKS_PER_FRAME);
+        // The rest of that second is gone rather than spread over later frames
+        assert_eq!(clock.advance(TICK_DT * 1.5), 1);
+    }
+
+    #[test]
+    fn paused_clock_only_runs_single_steps() {
+        let mut clock = Clock::new();
+        clock.paused = true;
+        assert_eq!(clock.advance(1.0), 0);
+        assert_eq!(clock.advance(TICK_DT), 0);
+
+        clock.step = true;
+        assert_eq!(clock.advance(1.0), 1);
+        assert_eq!(clock.advance(1.0), 0);
+    }
+}
@@ -0,0 +1,22 @@
+// Conversions between the sRGB values stored in the frame buffer and linear
+// light. Light adds up and scales in linear space, so darkening, blurring and
+// blending done on raw sRGB values comes out too dark.
+
+pub fn srgb_to_linear(value: u8) -> f32 {
+    let c = value as f32 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// Takes linear light in 0.0..=1.0 and returns the sRGB value, also 0.0..=1.0
+pub fn linear_to_srgb(value: f32) -> f32 {
+    let c = value.clamp(0.0, 1.0);
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
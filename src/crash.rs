@@ -0,0 +1,40 @@
+// Panic hook that leaves a crash report behind. The report has the panic
+// message, where it happened, how long the engine had been running and a full
+// backtrace, and is written to crash-<timestamp>.txt next to where the engine
+// was started. The default hook still runs afterwards so the panic shows up
+// on stderr like before.
+
+use std::backtrace::Backtrace;
+use std::fs;
+use std::panic;
+use std::thread;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+pub fn install() {
+    let started = Instant::now();
+    let default_hook = panic::take_hook();
+
+    panic::set_hook(Box::new(move |info| {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let path = format!("crash-{}.txt", timestamp);
+        let report = format!(
+            "{} {} crashed\n\
+             thread: {}\n\
+             uptime: {:.1}s\n\
+             {}\n\n\
+             backtrace:\n{}\n",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION"),
+            thread::current().name().unwrap_or("unnamed"),
+            started.elapsed().as_secs_f32(),
+            info,
+            Backtrace::force_capture(),
+        );
+
+        match fs::write(&path, report) {
+            Ok(()) => eprintln!("Crash report written to {}", path),
+            Err(err) => eprintln!("Couldn't write crash report {}: {}", path, err),
+        }
+        default_hook(info);
+    }));
+}
@@ -0,0 +1,177 @@
+// Ambient birds flying around in a flock. Each boid only looks at the boids
+// near it and steers by three rules: keep some distance from them
+// (separation), fly the same way they do (alignment) and stay close to the
+// group (cohesion). Together that's enough for convincing flocking.
+// On top of that they look ahead for voxels and turn away before hitting them.
+
+use crate::raycast;
+
+const NEIGHBOUR_RADIUS: f32 = 50.0;
+const SEPARATION_RADIUS: f32 = 16.0;
+const SEPARATION_WEIGHT: f32 = 1.5;
+const ALIGNMENT_WEIGHT: f32 = 0.05;
+const COHESION_WEIGHT: f32 = 0.005;
+// Boids further than this from home start turning back towards it
+const HOME_RADIUS: f32 = 250.0;
+const HOME_WEIGHT: f32 = 0.001;
+// How far ahead boids look for voxels in world pixels, and how hard they
+// turn away from one right in front of them
+const LOOKAHEAD: f32 = 40.0;
+const AVOID_WEIGHT: f32 = 0.5;
+// Speeds are in world pixels per tick
+const MIN_SPEED: f32 = 1.0;
+const MAX_SPEED: f32 = 3.0;
+
+pub struct Boid {
+    pub pos: (f32, f32),
+    pub vel: (f32, f32),
+}
+
+pub struct Flock {
+    pub boids: Vec<Boid>,
+}
+
+impl Flock {
+
+    pub fn new() -> Self {
+        Self { boids: Vec::new() }
+    }
+
+    // Adds boids on a spiral around `center`, flying around it
+    pub fn spawn(&mut self, center: (f32, f32), count: usize) {
+        // The golden angle spreads the boids out evenly without needing
+        // any randomness
+        const GOLDEN_ANGLE: f32 = 2.399_963;
+        let start = self.boids.len();
+        for i in start..start + count {
+            let angle = i as f32 * GOLDEN_ANGLE;
+            let dist = 6.0 * (i as f32).sqrt();
+            let (sin, cos) = angle.sin_cos();
+            self.boids.push(Boid {
+                pos: (center.0 + cos * dist, center.1 + sin * dist),
+                vel: (-sin * MAX_SPEED * 0.5, cos * MAX_SPEED * 0.5),
+            });
+        }
+    }
+
+    // Advances every boid by one tick, keeping the flock around `home` and out
+    // of solid cells
+    pub fn tick(&mut self, home: (f32, f32), is_solid: impl Fn((i32, i32)) -> bool, voxel_size: f32) {
+        // Work out all the steering first so every boid reacts to where the
+        // others were at the start of the tick
+        let steering: Vec<(f32, f32)> = self
+            .boids
+            .iter()
+            .map(|boid| self.steer(boid, home, &is_solid, voxel_size))
+            .collect();
+        let cell_at = |x: f32, y: f32| ((x / voxel_size).floor() as i32, (y / voxel_size).floor() as i32);
+
+        for (boid, acc) in self.boids.iter_mut().zip(steering) {
+            boid.vel.0 += acc.0;
+            boid.vel.1 += acc.1;
+
+            let speed = (boid.vel.0 * boid.vel.0 + boid.vel.1 * boid.vel.1).sqrt();
+            if speed > 0.0 {
+                let clamped = speed.clamp(MIN_SPEED, MAX_SPEED);
+                boid.vel.0 *= clamped / speed;
+                boid.vel.1 *= clamped / speed;
+            }
+
+            // Steering usually turns boids in time, but when it doesn't they
+            // bounce off the voxel instead of flying into it
+            if is_solid(cell_at(boid.pos.0 + boid.vel.0, boid.pos.1)) {
+                boid.vel.0 = -boid.vel.0;
+            } else {
+                boid.pos.0 += boid.vel.0;
+            }
+            if is_solid(cell_at(boid.pos.0, boid.pos.1 + boid.vel.1)) {
+                boid.vel.1 = -boid.vel.1;
+            } else {
+                boid.pos.1 += boid.vel.1;
+            }
+        }
+    }
+
+    fn steer(&self, boid: &Boid, home: (f32, f32), is_solid: &impl Fn((i32, i32)) -> bool, voxel_size: f32) -> (f32, f32) {
+        let mut separation = (0.0, 0.0);
+        let mut avg_vel = (0.0, 0.0);
+        let mut avg_pos = (0.0, 0.0);
+        let mut neighbours = 0;
+
+        for other in &self.boids {
+            let dx = boid.pos.0 - other.pos.0;
+            let dy = boid.pos.1 - other.pos.1;
+            let dist_sq = dx * dx + dy * dy;
+            // Also skips the boid itself
+            if dist_sq == 0.0 || dist_sq > NEIGHBOUR_RADIUS * NEIGHBOUR_RADIUS {
+                continue;
+            }
+            // Push away harder the closer the other boid is
+            if dist_sq < SEPARATION_RADIUS * SEPARATION_RADIUS {
+                separation.0 += dx / dist_sq;
+                separation.1 += dy / dist_sq;
+            }
+            avg_vel.0 += other.vel.0;
+            avg_vel.1 += other.vel.1;
+            avg_pos.0 += other.pos.0;
+            avg_pos.1 += other.pos.1;
+            neighbours += 1;
+        }
+
+        let mut acc = (separation.0 * SEPARATION_WEIGHT, separation.1 * SEPARATION_WEIGHT);
+        if neighbours > 0 {
+            let n = neighbours as f32;
+            acc.0 += (avg_vel.0 / n - boid.vel.0) * ALIGNMENT_WEIGHT;
+            acc.1 += (avg_vel.1 / n - boid.vel.1) * ALIGNMENT_WEIGHT;
+            acc.0 += (avg_pos.0 / n - boid.pos.0) * COHESION_WEIGHT;
+            acc.1 += (avg_pos.1 / n - boid.pos.1) * COHESION_WEIGHT;
+        }
+
+        let to_home = (home.0 - boid.pos.0, home.1 - boid.pos.1);
+        if to_home.0 * to_home.0 + to_home.1 * to_home.1 > HOME_RADIUS * HOME_RADIUS {
+            acc.0 += to_home.0 * HOME_WEIGHT;
+            acc.1 += to_home.1 * HOME_WEIGHT;
+        }
+
+        // Turn away from the face of the voxel ahead, harder the closer it is
+        if let Some(hit) = raycast::raycast(is_solid, voxel_size, boid.pos, boid.vel, LOOKAHEAD) {
+            let urgency = 1.0 - hit.distance / LOOKAHEAD;
+            acc.0 += hit.normal.0 as f32 * urgency * AVOID_WEIGHT;
+            acc.1 += hit.normal.1 as f32 * urgency * AVOID_WEIGHT;
+        }
+        acc
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn boid_does_not_fly_into_a_wall() {
+        // A wall of 8 pixel voxels along x = 80..88
+        let is_solid = |cell: (i32, i32)| cell.0 == 10;
+        let mut flock = Flock::new();
+        flock.boids.push(Boid { pos: (40.0, 4.0), vel: (MAX_SPEED, 0.0) });
+
+        for _ in 0..200 {
+            flock.tick((40.0, 4.0), is_solid, 8.0);
+            let pos = flock.boids[0].pos;
+            let cell = ((pos.0 / 8.0).floor() as i32, (pos.1 / 8.0).floor() as i32);
+            assert!(!is_solid(cell), "boid flew into {:?}", cell);
+        }
+        // It turned around rather than just stopping at the wall
+        assert!(flock.boids[0].vel.0 < 0.0 || flock.boids[0].pos.0 < 80.0);
+    }
+
+    #[test]
+    fn boid_steers_away_before_reaching_a_wall() {
+        let is_solid = |cell: (i32, i32)| cell.0 == 10;
+        let mut flock = Flock::new();
+        flock.boids.push(Boid { pos: (56.0, 4.0), vel: (MAX_SPEED, 0.0) });
+
+        let start = flock.boids[0].vel.0;
+        flock.tick((56.0, 4.0), is_solid, 8.0);
+        assert!(flock.boids[0].vel.0 < start);
+    }
+}
@@ -0,0 +1,131 @@
+// Field of view using recursive shadowcasting (Björn Bergström's algorithm as
+// described on RogueBasin). The area around the origin is split into eight
+// octants and each one is scanned row by row, moving away from the origin.
+// When a row contains opaque cells the scan recurses with a narrower slope
+// range, so everything behind them stays in shadow.
+
+use std::collections::HashSet;
+
+// Maps the coordinates of the first octant onto each of the eight octants,
+// as (xx, xy, yx, yy)
+const OCTANTS: [(i32, i32, i32, i32); 8] = [
+    (1, 0, 0, 1),
+    (0, 1, 1, 0),
+    (0, -1, 1, 0),
+    (-1, 0, 0, 1),
+    (-1, 0, 0, -1),
+    (0, -1, -1, 0),
+    (0, 1, -1, 0),
+    (1, 0, 0, -1),
+];
+
+// Returns every cell within `radius` cells of `origin` that can be seen from
+// it. Opaque cells that are seen are included, they just hide what's behind.
+pub fn compute(origin: (i32, i32), radius: i32, is_opaque: impl Fn((i32, i32)) -> bool) -> HashSet<(i32, i32)> {
+    let mut visible = HashSet::new();
+    visible.insert(origin);
+    for octant in OCTANTS {
+        cast_light(&mut visible, &is_opaque, origin, radius, 1, 1.0, 0.0, octant);
+    }
+    visible
+}
+
+#[allow(clippy::too_many_arguments)]
+fn cast_light(
+    visible: &mut HashSet<(i32, i32)>,
+    is_opaque: &impl Fn((i32, i32)) -> bool,
+    origin: (i32, i32),
+    radius: i32,
+    row: i32,
+    mut start: f32,
+    end: f32,
+    (xx, xy, yx, yy): (i32, i32, i32, i32),
+) {
+    if start < end {
+        return;
+    }
+
+    let mut new_start = 0.0;
+    for distance in row..=radius {
+        let dy = -distance;
+        let mut blocked = false;
+
+        for dx in -distance..=0 {
+            // Slopes of the left and right edges of this cell
+            let l_slope = (dx as f32 - 0.5) / (dy as f32 + 0.5);
+            let r_slope = (dx as f32 + 0.5) / (dy as f32 - 0.5);
+            if start < r_slope {
+                continue;
+            }
+            if end > l_slope {
+                break;
+            }
+
+            let cell = (origin.0 + dx * xx + dy * xy, origin.1 + dx * yx + dy * yy);
+            if dx * dx + dy * dy <= radius * radius {
+                visible.insert(cell);
+            }
+
+            if blocked {
+                // Still scanning along a run of opaque cells
+                if is_opaque(cell) {
+                    new_start = r_slope;
+                } else {
+                    blocked = false;
+                    start = new_start;
+                }
+            } else if is_opaque(cell) && distance < radius {
+                // Start of a run of opaque cells, whatever is visible past
+                // its left edge gets scanned separately
+                blocked = true;
+                cast_light(visible, is_opaque, origin, radius, distance + 1, start, l_slope, (xx, xy, yx, yy));
+                new_start = r_slope;
+            }
+        }
+
+        if blocked {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn open_ground_is_visible_within_the_radius() {
+        let radius = 6;
+        let visible = compute((3, -2), radius, |_| false);
+        let expected: HashSet<_> = (-radius..=radius)
+            .flat_map(|dy| (-radius..=radius).map(move |dx| (dx, dy)))
+            .filter(|&(dx, dy)| dx * dx + dy * dy <= radius * radius)
+            .map(|(dx, dy)| (3 + dx, -2 + dy))
+            .collect();
+        assert_eq!(visible, expected);
+    }
+
+    #[test]
+    fn walls_are_seen_but_hide_what_is_behind() {
+        let visible = compute((0, 0), 8, |(x, y)| x == 2 && (-10..=10).contains(&y));
+        assert!(visible.contains(&(2, 0)));
+        assert!(visible.contains(&(1, 5)));
+        assert!(visible.iter().all(|&(x, _)| x <= 2));
+    }
+
+    #[test]
+    fn pillars_cast_a_shadow_in_one_direction_only() {
+        let visible = compute((0, 0), 8, |cell| cell == (2, 0));
+        assert!(visible.contains(&(2, 0)));
+        assert!(!visible.contains(&(4, 0)));
+        assert!(!visible.contains(&(6, 0)));
+        for cell in [(-4, 0), (0, 4), (0, -4), (4, 4)] {
+            assert!(visible.contains(&cell), "{:?} should be visible", cell);
+        }
+    }
+
+    #[test]
+    fn the_origin_is_always_visible() {
+        assert!(compute((5, 5), 3, |_| true).contains(&(5, 5)));
+    }
+}
@@ -0,0 +1,185 @@
+// Generators for structures that get stamped into the world: hedge mazes and
+// dungeons made of rooms joined by corridors. Both produce a Layout of solid
+// and empty cells which the world copies over its own voxels.
+
+use crate::rng::Rng;
+
+pub struct Layout {
+    pub width: i32,
+    pub height: i32,
+    // An open cell inside the layout, where the player gets placed
+    pub start: (i32, i32),
+    solid: Vec<bool>,
+}
+
+impl Layout {
+
+    // Wraps cells generated elsewhere, starting at the open cell closest to
+    // the middle
+    pub fn new(width: i32, height: i32, solid: Vec<bool>) -> Self {
+        let mut layout = Self { width, height, start: (width / 2, height / 2), solid };
+        let open = (0..width * height)
+            .map(|i| (i % width, i / width))
+            .filter(|&(x, y)| !layout.is_solid(x, y))
+            .min_by_key(|&(x, y)| (x - width / 2).pow(2) + (y - height / 2).pow(2));
+        if let Some(start) = open {
+            layout.start = start;
+        }
+        layout
+    }
+
+    fn filled(width: i32, height: i32) -> Self {
+        Self { width, height, start: (0, 0), solid: vec![true; (width * height) as usize] }
+    }
+
+    pub fn is_solid(&self, x: i32, y: i32) -> bool {
+        self.solid[(y * self.width + x) as usize]
+    }
+
+    fn carve(&mut self, x: i32, y: i32) {
+        self.solid[(y * self.width + x) as usize] = false;
+    }
+}
+
+// Perfect maze using a recursive backtracker. Passages sit on odd cells and
+// the walls between them on even ones, so the size gets rounded up to odd.
+pub fn maze(width: i32, height: i32, rng: &mut Rng) -> Layout {
+    let width = width | 1;
+    let height = height | 1;
+    let mut layout = Layout::filled(width, height);
+
+    let mut stack = vec![(1, 1)];
+    layout.carve(1, 1);
+    while let Some(&(x, y)) = stack.last() {
+        let neighbours: Vec<(i32, i32)> = [(2, 0), (-2, 0), (0, 2), (0, -2)]
+            .into_iter()
+            .map(|(dx, dy)| (x + dx, y + dy))
+            .filter(|&(nx, ny)| nx > 0 && nx < width - 1 && ny > 0 && ny < height - 1)
+            .filter(|&(nx, ny)| layout.is_solid(nx, ny))
+            .collect();
+
+        if neighbours.is_empty() {
+            stack.pop();
+            continue;
+        }
+        let (nx, ny) = neighbours[rng.range(0, neighbours.len() as i32 - 1) as usize];
+        // Knock down the wall between the two cells
+        layout.carve((x + nx) / 2, (y + ny) / 2);
+        layout.carve(nx, ny);
+        stack.push((nx, ny));
+    }
+
+    // Entrance on the left and exit on the right
+    layout.carve(0, 1);
+    layout.carve(width - 1, height - 2);
+    // Cells with odd coordinates are always part of a passage
+    layout.start = ((width / 2) | 1, (height / 2) | 1);
+    layout
+}
+
+// Dungeon made by splitting the area in two over and over (binary space
+// partitioning), putting a room in every leaf and joining sibling rooms
+pub fn dungeon(width: i32, height: i32, rng: &mut Rng) -> Layout {
+    let mut layout = Layout::filled(width, height);
+    // Leave the outer ring solid so the dungeon is closed off
+    layout.start = split(&mut layout, (1, 1, width - 2, height - 2), rng);
+    layout
+}
+
+const MIN_LEAF: i32 = 8;
+
+// Carves out the rooms for an area (x, y, width, height) and returns the
+// center of one of them so the caller can run a corridor to it
+fn split(layout: &mut Layout, area: (i32, i32, i32, i32), rng: &mut Rng) -> (i32, i32) {
+    let (x, y, w, h) = area;
+    let can_split_x = w >= MIN_LEAF * 2;
+    let can_split_y = h >= MIN_LEAF * 2;
+
+    if !can_split_x && !can_split_y {
+        // Leaf: a room with at least a one cell border inside the area
+        let room_w = rng.range(3.min(w - 2), w - 2);
+        let room_h = rng.range(3.min(h - 2), h - 2);
+        let room_x = x + rng.range(1, w - room_w - 1);
+        let room_y = y + rng.range(1, h - room_h - 1);
+        for cy in room_y..room_y + room_h {
+            for cx in room_x..room_x + room_w {
+                layout.carve(cx, cy);
+            }
+        }
+        return (room_x + room_w / 2, room_y + room_h / 2);
+    }
+
+    // Split across the longer side when both are possible
+    let split_x = if can_split_x && can_split_y { w >= h } else { can_split_x };
+    let (a, b) = if split_x {
+        let cut = rng.range(MIN_LEAF, w - MIN_LEAF);
+        ((x, y, cut, h), (x + cut, y, w - cut, h))
+    } else {
+        let cut = rng.range(MIN_LEAF, h - MIN_LEAF);
+        ((x, y, w, cut), (x, y + cut, w, h - cut))
+    };
+
+    let from = split(layout, a, rng);
+    let to = split(layout, b, rng);
+    corridor(layout, from, to);
+    from
+}
+
+// L shaped corridor, horizontal first and then vertical
+fn corridor(layout: &mut Layout, from: (i32, i32), to: (i32, i32)) {
+    for x in from.0.min(to.0)..=from.0.max(to.0) {
+        layout.carve(x, from.1);
+    }
+    for y in from.1.min(to.1)..=from.1.max(to.1) {
+        layout.carve(to.0, y);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every open cell that can be walked to from the start
+    fn reachable(layout: &Layout) -> Vec<(i32, i32)> {
+        let mut seen = vec![false; (layout.width * layout.height) as usize];
+        let mut stack = vec![layout.start];
+        let mut cells = Vec::new();
+        while let Some((x, y)) = stack.pop() {
+            if x < 0 || y < 0 || x >= layout.width || y >= layout.height || layout.is_solid(x, y) {
+                continue;
+            }
+            let index = (y * layout.width + x) as usize;
+            if seen[index] {
+                continue;
+            }
+            seen[index] = true;
+            cells.push((x, y));
+            stack.extend([(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)]);
+        }
+        cells
+    }
+
+    fn check(generate: impl Fn(&mut Rng) -> Layout) {
+        for seed in [1, 42, 0x5eed, 123456789] {
+            let layout = generate(&mut Rng::new(seed));
+            assert_eq!(layout.solid, generate(&mut Rng::new(seed)).solid, "seed {}", seed);
+            assert!(!layout.is_solid(layout.start.0, layout.start.1), "seed {}", seed);
+
+            let open = layout.solid.iter().filter(|&&solid| !solid).count();
+            assert_eq!(reachable(&layout).len(), open, "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn mazes_are_repeatable_and_connected() {
+        check(|rng| maze(21, 15, rng));
+        // Even sizes get rounded up
+        check(|rng| maze(20, 20, rng));
+    }
+
+    #[test]
+    fn dungeons_are_repeatable_and_connected() {
+        check(|rng| dungeon(48, 36, rng));
+        check(|rng| dungeon(20, 60, rng));
+    }
+}
@@ -1,3 +1,28 @@
+mod benchmark;
+mod campath;
+mod camera;
+mod clock;
+mod color;
+mod crash;
+mod flock;
+mod fov;
+mod generate;
+mod map;
+mod postprocess;
+mod profiler;
+mod raycast;
+mod recorder;
+mod rng;
+mod scene;
+mod settings;
+mod shapes;
+mod theme;
+mod wfc;
+
+use std::collections::HashSet;
+use std::process;
+use std::time::Instant;
+
 use winit::{
     event::{Event, WindowEvent},
     dpi::LogicalSize,
@@ -7,22 +32,210 @@ use winit::{
 };
 use pixels::{Pixels, SurfaceTexture};
 use winit_input_helper::WinitInputHelper;
+use benchmark::Benchmark;
+use camera::Camera;
+use campath::CameraPath;
+use clock::{Clock, TICK_DT};
+use flock::Flock;
+use generate::Layout;
+use map::MapExport;
+use postprocess::{Effect, PostProcess};
+use profiler::Profiler;
+use raycast::RayHit;
+use recorder::Recorder;
+use rng::Rng;
+use scene::{Action, Scene, Trigger};
+use settings::Settings;
+use theme::{Theme, Themes};
 
+const TITLE: &str = "Tiny Voxel Engine";
 const WIDTH: u32 = 640;
 const HEIGHT: u32 = 480;
-const SIZE: u32 = 16;
+// Seconds the mouse has to be held on a voxel to break it
+const BREAK_TIME: f32 = 0.6;
+// How much of the voxel color shows through in the placement ghost
+const GHOST_ALPHA: f32 = 0.4;
+// Seconds a trigger's message stays in the title
+const MESSAGE_TIME: f32 = 4.0;
+// Anti-aliasing pass that F adds to or takes out of the post chain
+const FXAA: Effect = Effect::Fxaa { span: 8.0 };
+// How strongly the retro dither pass (K) scatters colors
+const DITHER_SPREAD: f32 = 0.25;
+// Retro monitor look that C toggles
+const CRT: Effect = Effect::Crt { curvature: 0.06, scanlines: 0.35, aberration: 1.5 };
+const GAMMA_STEP: f32 = 0.1;
+const MIN_GAMMA: f32 = 0.5;
+const MAX_GAMMA: f32 = 2.5;
 
+// z is unused until the world gets a third dimension
+#[allow(dead_code)]
 struct Vec3 {x: f32, y:f32, z: f32}
 
+// Keys that move a player around, every local player has their own set
+struct Controls {
+    up: KeyCode,
+    down: KeyCode,
+    left: KeyCode,
+    right: KeyCode,
+    sprint: KeyCode,
+    crouch: KeyCode
+}
+
+const WASD: Controls = Controls {
+    up: KeyCode::KeyW,
+    down: KeyCode::KeyS,
+    left: KeyCode::KeyA,
+    right: KeyCode::KeyD,
+    sprint: KeyCode::ShiftLeft,
+    crouch: KeyCode::ControlLeft,
+};
+
+const ARROWS: Controls = Controls {
+    up: KeyCode::ArrowUp,
+    down: KeyCode::ArrowDown,
+    left: KeyCode::ArrowLeft,
+    right: KeyCode::ArrowRight,
+    sprint: KeyCode::ShiftRight,
+    crouch: KeyCode::ControlRight,
+};
+
+#[derive(Clone, Copy)]
+enum Stance {
+    Walking,
+    Sprinting,
+    Crouching
+}
+
 struct Player {
     pos: Vec3,
-    radius: i32
+    radius: i32,
+    controls: Controls,
+    stance: Stance,
+    stamina: f32, // 0.0 to 1.0, sprinting uses it up
+    winded: bool // Ran dry and can't sprint until some of it is back
+}
+
+impl Player {
+
+    fn new(pos: (f32, f32), controls: Controls) -> Self {
+        Self {
+            pos: Vec3 { x: pos.0, y: pos.1, z: 0.0 },
+            radius: 10,
+            controls,
+            stance: Stance::Walking,
+            stamina: 1.0,
+            winded: false,
+        }
+    }
+
+    // Sprinting drains stamina, anything else refills it and standing still
+    // refills it fastest. Once it runs out the player has to get back to
+    // RECOVERED before sprinting again, so sprint doesn't flicker on and off.
+    fn update_stamina(&mut self, moving: bool) {
+        const DRAIN: f32 = 0.25; // Per second, so four seconds of sprinting
+        const REFILL_MOVING: f32 = 0.1;
+        const REFILL_IDLE: f32 = 0.3;
+        const RECOVERED: f32 = 0.3;
+
+        let rate = match self.stance {
+            Stance::Sprinting => -DRAIN,
+            _ if moving => REFILL_MOVING,
+            _ => REFILL_IDLE,
+        };
+        self.stamina = (self.stamina + rate * TICK_DT).clamp(0.0, 1.0);
+        if self.stamina == 0.0 {
+            self.winded = true;
+        } else if self.stamina >= RECOVERED {
+            self.winded = false;
+        }
+    }
+
+    // Distance moved per tick
+    fn speed(&self) -> f32 {
+        const SPEED: f32 = 2.0;
+        match self.stance {
+            Stance::Walking => SPEED,
+            Stance::Sprinting => SPEED * 1.75,
+            Stance::Crouching => SPEED * 0.5,
+        }
+    }
+
+    // Crouching makes the player smaller
+    fn size(&self) -> i32 {
+        match self.stance {
+            Stance::Crouching => self.radius * 2 / 3,
+            _ => self.radius,
+        }
+    }
+}
+
+// Rectangle of the frame that one player's view is drawn into
+#[derive(Clone, Copy)]
+struct Viewport {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32
+}
+
+impl Viewport {
+
+    // Converts a position on the window into one relative to this viewport
+    fn local(&self, p: (f32, f32)) -> Option<(f32, f32)> {
+        let x = p.0 - self.x as f32;
+        let y = p.1 - self.y as f32;
+        if x >= 0.0 && x < self.width as f32 && y >= 0.0 && y < self.height as f32 {
+            Some((x, y))
+        } else {
+            None
+        }
+    }
+
+    // Writes a pixel given in viewport coordinates, anything outside of the
+    // viewport is clipped so views can't draw over each other
+    fn put(&self, frame: &mut [u8], x: i32, y: i32, color: &[u8; 4]) {
+        if let Some(pixel) = self.pixel_mut(frame, x, y) {
+            pixel.copy_from_slice(color);
+        }
+    }
+
+    fn pixel_mut<'a>(&self, frame: &'a mut [u8], x: i32, y: i32) -> Option<&'a mut [u8]> {
+        if x >= 0 && x < self.width as i32 && y >= 0 && y < self.height as i32 {
+            let index = (((self.y + y as u32) * WIDTH + self.x + x as u32) * 4) as usize;
+            Some(&mut frame[index..index + 4])
+        } else {
+            None
+        }
+    }
+}
+
+// What a local player sees: a camera following them and where it's drawn
+struct View {
+    camera: Camera,
+    viewport: Viewport
+}
+
+struct Grid {
+    visible: bool,
+    cell_size: f32, // Spacing of the major lines in world pixels
+    subdivisions: u32 // Minor lines drawn inside each cell
 }
 
 struct World {
     voxel_size: usize, // Size of the voxel in pixels
-    player: Player,
-    cursor: Option<(f32, f32)>
+    voxels: HashSet<(i32, i32)>, // Cells that are solid
+    players: Vec<Player>,
+    views: Vec<View>, // One per player, in the same order
+    flock: Flock,
+    grid: Grid,
+    fov_enabled: bool, // Dim the cells players can't see
+    theme: Theme,
+    cursor: Option<(f32, f32)>,
+    breaking: Option<((i32, i32), f32)>, // Voxel being broken and progress, 0.0 to 1.0
+    anchor: Option<(i32, i32)>, // First cell of the shape being built
+    triggers: Vec<Trigger>,
+    inside: HashSet<(usize, usize)>, // Trigger and player index of everyone inside one
+    fired: Vec<Action> // Trigger actions for the main loop to carry out
 }
 
 impl World {
@@ -30,43 +243,265 @@ impl World {
     fn draw(&self, frame: &mut [u8]) {
 
         for pixel in frame.chunks_exact_mut(4) {
-            // White background: [Red, Green, Blue, Alpha]
-            pixel.copy_from_slice(&[0x00, 0x00, 0x00, 0x00]);
+            pixel.copy_from_slice(&self.theme.background);
         }
 
-        self.draw_gridlines(frame);
-        self.draw_player(frame);
+        for (i, view) in self.views.iter().enumerate() {
+            self.draw_voxels(frame, view);
+            self.draw_cracks(frame, view);
+            self.draw_anchor(frame, view);
+            if i == 0 {
+                self.draw_ghost(frame, view);
+            }
+            self.draw_gridlines(frame, view);
+            self.draw_flock(frame, view);
+            for (player, color) in self.players.iter().zip(&self.theme.players) {
+                self.draw_player(frame, view, player, color);
+            }
+            if self.fov_enabled {
+                self.draw_shadows(frame, view, &self.players[i]);
+            }
+            self.draw_stamina(frame, view, &self.players[i], &self.theme.players[i]);
+
+            // Only the first player aims with the mouse
+            if i == 0 && let Some(cursor_pos) = self.cursor.and_then(|c| view.viewport.local(c)) {
+                self.draw_line(frame, view, &self.players[0], cursor_pos);
+            }
+        }
 
-        if let Some(cursor_pos) = self.cursor {
-            self.draw_line(frame, cursor_pos);
+        // Separate the halves of the screen when playing split-screen
+        for view in self.views.iter().skip(1) {
+            for y in 0..view.viewport.height as i32 {
+                view.viewport.put(frame, 0, y, &self.theme.divider);
+            }
         }
     }
 
-    fn draw_gridlines(&self, frame: &mut [u8]) {
-        let grid_color = [0x00, 0x00, 0xff, 0xff];
-    
-        for y in (0..HEIGHT).step_by(self.voxel_size as usize) {
-            for x in 0..WIDTH {
-                let index = ((y * WIDTH + x) * 4) as usize;
-                frame[index..index + 4].copy_from_slice(&grid_color);
+    fn draw_gridlines(&self, frame: &mut [u8], view: &View) {
+        if !self.grid.visible {
+            return;
+        }
+
+        // Minor lines first so the major lines end up drawn on top of them
+        if self.grid.subdivisions > 1 {
+            self.draw_grid_spacing(frame, view, self.grid.cell_size / self.grid.subdivisions as f32, self.theme.grid_minor);
+        }
+        self.draw_grid_spacing(frame, view, self.grid.cell_size, self.theme.grid_major);
+    }
+
+    fn draw_grid_spacing(&self, frame: &mut [u8], view: &View, spacing: f32, color: [u8; 4]) {
+        // Gridlines sit on world coordinates that are multiples of the spacing,
+        // so shift them by wherever the camera currently is
+        let origin = view.camera.origin();
+        let width = view.viewport.width as i32;
+        let height = view.viewport.height as i32;
+
+        let mut k = (origin.1 / spacing).ceil();
+        loop {
+            let y = (k * spacing - origin.1).round() as i32;
+            if y >= height {
+                break;
+            }
+            for x in 0..width {
+                view.viewport.put(frame, x, y, &color);
             }
+            k += 1.0;
         }
-    
-        for x in (0..WIDTH).step_by(self.voxel_size as usize) {
-            for y in 0..HEIGHT {
-                let index = ((y * WIDTH + x) * 4) as usize;
-                frame[index..index + 4].copy_from_slice(&grid_color);
+
+        let mut k = (origin.0 / spacing).ceil();
+        loop {
+            let x = (k * spacing - origin.0).round() as i32;
+            if x >= width {
+                break;
+            }
+            for y in 0..height {
+                view.viewport.put(frame, x, y, &color);
+            }
+            k += 1.0;
+        }
+    }
+
+    fn draw_voxels(&self, frame: &mut [u8], view: &View) {
+        let (first, last) = self.cells_in_view(view);
+
+        for cy in first.1..=last.1 {
+            for cx in first.0..=last.0 {
+                if !self.is_solid((cx, cy)) {
+                    continue;
+                }
+                let (x0, y0) = self.cell_corner(view, (cx, cy));
+                for y in y0..y0 + self.voxel_size as i32 {
+                    for x in x0..x0 + self.voxel_size as i32 {
+                        view.viewport.put(frame, x, y, &self.theme.voxel);
+                    }
+                }
+            }
+        }
+    }
+
+    // Cracks spreading from the middle of the voxel that is being broken
+    fn draw_cracks(&self, frame: &mut [u8], view: &View) {
+        // Each crack is a few points relative to the cell center, in cells
+        const CRACKS: [[(f32, f32); 3]; 5] = [
+            [(0.0, 0.0), (0.15, -0.2), (0.3, -0.42)],
+            [(0.0, 0.0), (0.22, 0.05), (0.45, 0.12)],
+            [(0.0, 0.0), (0.05, 0.25), (-0.1, 0.44)],
+            [(0.0, 0.0), (-0.2, 0.08), (-0.43, -0.05)],
+            [(0.0, 0.0), (-0.1, -0.18), (-0.08, -0.4)],
+        ];
+
+        let Some((cell, progress)) = self.breaking else {
+            return;
+        };
+        let size = self.voxel_size as f32;
+        let center = ((cell.0 as f32 + 0.5) * size, (cell.1 as f32 + 0.5) * size);
+        // The cracks grow outwards as the voxel gets closer to breaking
+        let point = |(x, y): (f32, f32)| {
+            view.camera.world_to_screen((center.0 + x * size * progress, center.1 + y * size * progress))
+        };
+        for crack in CRACKS {
+            for pair in crack.windows(2) {
+                self.draw_segment(frame, view, point(pair[0]), point(pair[1]), &self.theme.background);
+            }
+        }
+    }
+
+    // Darkens every cell that the player can't see from where they stand
+    fn draw_shadows(&self, frame: &mut [u8], view: &View, player: &Player) {
+        const FOV_RADIUS: i32 = 12;
+        const SHADOW: f32 = 0.1; // Fraction of the light left in the shadows
+
+        // Darken in linear light, scaling the sRGB values directly would
+        // crush everything in the shadows to black
+        let darken: [u8; 256] = std::array::from_fn(|c| {
+            let linear = color::srgb_to_linear(c as u8) * SHADOW;
+            (color::linear_to_srgb(linear) * 255.0).round() as u8
+        });
+
+        let origin = self.cell_at((player.pos.x, player.pos.y));
+        let visible = fov::compute(origin, FOV_RADIUS, |cell| self.is_solid(cell));
+        let (first, last) = self.cells_in_view(view);
+
+        for cy in first.1..=last.1 {
+            for cx in first.0..=last.0 {
+                if visible.contains(&(cx, cy)) {
+                    continue;
+                }
+                let (x0, y0) = self.cell_corner(view, (cx, cy));
+                for y in y0..y0 + self.voxel_size as i32 {
+                    for x in x0..x0 + self.voxel_size as i32 {
+                        if let Some(pixel) = view.viewport.pixel_mut(frame, x, y) {
+                            for channel in &mut pixel[..3] {
+                                *channel = darken[*channel as usize];
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // First and last cell that overlap the viewport on each axis
+    fn cells_in_view(&self, view: &View) -> ((i32, i32), (i32, i32)) {
+        let origin = view.camera.origin();
+        let first = self.cell_at(origin);
+        let last = self.cell_at((
+            origin.0 + view.viewport.width as f32,
+            origin.1 + view.viewport.height as f32,
+        ));
+        (first, last)
+    }
+
+    // Outlines the shape anchor so it's clear where the next shape starts
+    fn draw_anchor(&self, frame: &mut [u8], view: &View) {
+        let Some(cell) = self.anchor else {
+            return;
+        };
+        let (x0, y0) = self.cell_corner(view, cell);
+        let last = self.voxel_size as i32 - 1;
+        for i in 0..=last {
+            for (x, y) in [(x0 + i, y0), (x0 + i, y0 + last), (x0, y0 + i), (x0 + last, y0 + i)] {
+                view.viewport.put(frame, x, y, &self.theme.ray_hit);
+            }
+        }
+    }
+
+    // Faint voxel where a left click would place one, red if a player is in
+    // the way
+    fn draw_ghost(&self, frame: &mut [u8], view: &View) {
+        const BLOCKED: [u8; 4] = [0xe0, 0x30, 0x30, 0xff];
+
+        let Some(cell) = self.target(view) else {
+            return;
+        };
+        if self.is_solid(cell) {
+            return;
+        }
+        let color = if self.blocks_player(cell) { &BLOCKED } else { &self.theme.voxel };
+
+        // Mix in linear light like the shadows do
+        let tint: [f32; 3] = std::array::from_fn(|c| color::srgb_to_linear(color[c]) * GHOST_ALPHA);
+        let (x0, y0) = self.cell_corner(view, cell);
+        for y in y0..y0 + self.voxel_size as i32 {
+            for x in x0..x0 + self.voxel_size as i32 {
+                if let Some(pixel) = view.viewport.pixel_mut(frame, x, y) {
+                    for (channel, tint) in pixel[..3].iter_mut().zip(tint) {
+                        let linear = color::srgb_to_linear(*channel) * (1.0 - GHOST_ALPHA) + tint;
+                        *channel = (color::linear_to_srgb(linear) * 255.0).round() as u8;
+                    }
+                }
             }
         }
     }
 
-    fn draw_line(&self, frame: &mut [u8], cursor_pos: (f32, f32)) {
-        let line_color = [0x00, 0xff, 0x00, 0xff]; // Green line.
+    // Top left corner of a cell in viewport coordinates
+    fn cell_corner(&self, view: &View, cell: (i32, i32)) -> (i32, i32) {
+        let size = self.voxel_size as f32;
+        let corner = view.camera.world_to_screen((cell.0 as f32 * size, cell.1 as f32 * size));
+        (corner.0.round() as i32, corner.1.round() as i32)
+    }
+
+    // Casts a ray from the player towards the cursor, it stops at the first
+    // solid voxel and highlights the face it hit
+    fn draw_line(&self, frame: &mut [u8], view: &View, player: &Player, cursor_pos: (f32, f32)) {
+
+        let origin = (player.pos.x, player.pos.y);
+        let target = view.camera.screen_to_world(cursor_pos);
+        let dir = (target.0 - origin.0, target.1 - origin.1);
+        let max_dist = (dir.0 * dir.0 + dir.1 * dir.1).sqrt();
+
+        let start = view.camera.world_to_screen(origin);
+        let Some(hit) = self.raycast(origin, dir, max_dist) else {
+            self.draw_segment(frame, view, start, cursor_pos, &self.theme.ray);
+            return;
+        };
+        self.draw_segment(frame, view, start, view.camera.world_to_screen(hit.position), &self.theme.ray);
+
+        // The face is the edge of the hit cell on the side the normal points to
+        let size = self.voxel_size as f32;
+        let (cx, cy) = (hit.cell.0 as f32, hit.cell.1 as f32);
+        let (a, b) = match hit.normal {
+            (nx, 0) if nx != 0 => {
+                let x = if nx > 0 { cx + 1.0 } else { cx } * size;
+                ((x, cy * size), (x, (cy + 1.0) * size))
+            }
+            (0, ny) if ny != 0 => {
+                let y = if ny > 0 { cy + 1.0 } else { cy } * size;
+                ((cx * size, y), ((cx + 1.0) * size, y))
+            }
+            // Started inside a voxel, there's no face to show
+            _ => return,
+        };
+        self.draw_segment(frame, view, view.camera.world_to_screen(a), view.camera.world_to_screen(b), &self.theme.ray_hit);
+    }
+
+    // Bresenham line between two positions in viewport coordinates
+    fn draw_segment(&self, frame: &mut [u8], view: &View, from: (f32, f32), to: (f32, f32), color: &[u8; 4]) {
         // Round positions to integer pixel coordinates.
-        let x0 = self.player.pos.x.round() as i32;
-        let y0 = self.player.pos.y.round() as i32;
-        let x1 = cursor_pos.0.round() as i32;
-        let y1 = cursor_pos.1.round() as i32;
+        let x0 = from.0.round() as i32;
+        let y0 = from.1.round() as i32;
+        let x1 = to.0.round() as i32;
+        let y1 = to.1.round() as i32;
 
         let dx = (x1 - x0).abs();
         let sx = if x0 < x1 { 1 } else { -1 };
@@ -77,10 +512,7 @@ impl World {
         let mut current_y = y0;
 
         loop {
-            if current_x >= 0 && current_x < WIDTH as i32 && current_y >= 0 && current_y < HEIGHT as i32 {
-                let index = ((current_y as u32 * WIDTH + current_x as u32) * 4) as usize;
-                frame[index..index + 4].copy_from_slice(&line_color);
-            }
+            view.viewport.put(frame, current_x, current_y, color);
             if current_x == x1 && current_y == y1 {
                 break;
             }
@@ -96,62 +528,497 @@ impl World {
         }
     }
 
-    fn draw_player(&self, frame: &mut [u8]) {
+    fn draw_player(&self, frame: &mut [u8], view: &View, player: &Player, color: &[u8; 4]) {
 
-        let player_color = [0xff, 0x00, 0x00, 0xff];
-        let radius: i32 = self.player.radius;
+        let radius: i32 = player.size();
     
-        let center_x = self.player.pos.x as i32;
-        let center_y = self.player.pos.y as i32;
+        let center = view.camera.world_to_screen((player.pos.x, player.pos.y));
+        let center_x = center.0 as i32;
+        let center_y = center.1 as i32;
     
         for y in (center_y - radius)..=(center_y + radius) {
             for x in (center_x - radius)..=(center_x + radius) {
-                if x >= 0 && x < WIDTH as i32 && y >= 0 && y < HEIGHT as i32 {
-                    let dx = x - center_x;
-                    let dy = y - center_y;
-                    if dx * dx + dy * dy <= radius * radius {
-                        let index = ((y as u32 * WIDTH + x as u32) * 4) as usize;
-                        frame[index..index + 4].copy_from_slice(&player_color);
-                    }
+                let dx = x - center_x;
+                let dy = y - center_y;
+                if dx * dx + dy * dy <= radius * radius {
+                    view.viewport.put(frame, x, y, color);
                 }
             }
         }
     }    
 
-    // New function that handles the keyboard input for moving the player.
-    fn handle_input(&mut self, input: &WinitInputHelper) {
-        self.cursor = input.cursor().map(|(x, y)| (x as f32, y as f32));
-        const SPEED: f32 = 2.0;
-        // Move up (W): decrease y
-        if input.key_held(KeyCode::KeyW) {
-            self.player.pos.y -= SPEED;
+    // Stamina bar in the bottom left corner of the view, hidden while full
+    fn draw_stamina(&self, frame: &mut [u8], view: &View, player: &Player, color: &[u8; 4]) {
+        const WIDTH: i32 = 100;
+        const HEIGHT: i32 = 6;
+        const MARGIN: i32 = 8;
+
+        if player.stamina >= 1.0 {
+            return;
         }
-        // Move down (S): increase y
-        if input.key_held(KeyCode::KeyS) {
-            self.player.pos.y += SPEED;
+        let left = MARGIN;
+        let top = view.viewport.height as i32 - MARGIN - HEIGHT;
+        let filled = (player.stamina * WIDTH as f32).round() as i32;
+        // Grey while winded, to show sprinting is off
+        let fill = if player.winded { &self.theme.divider } else { color };
+        for y in top..top + HEIGHT {
+            for x in left..left + WIDTH {
+                let color = if x - left < filled { fill } else { &self.theme.grid_minor };
+                view.viewport.put(frame, x, y, color);
+            }
         }
-        // Move left (A): decrease x
-        if input.key_held(KeyCode::KeyA) {
-            self.player.pos.x -= SPEED;
+    }
+
+    fn draw_flock(&self, frame: &mut [u8], view: &View) {
+        // Each bird is a short streak trailing behind it
+        for boid in &self.flock.boids {
+            let head = view.camera.world_to_screen(boid.pos);
+            for step in 0..4 {
+                let x = head.0 - boid.vel.0 * step as f32;
+                let y = head.1 - boid.vel.1 * step as f32;
+                view.viewport.put(frame, x.round() as i32, y.round() as i32, &self.theme.bird);
+            }
         }
-        // Move right (D): increase x
-        if input.key_held(KeyCode::KeyD) {
-            self.player.pos.x += SPEED;
+    }
+
+    fn is_solid(&self, cell: (i32, i32)) -> bool {
+        self.voxels.contains(&cell)
+    }
+
+    // Voxel containing a position in world pixels
+    fn cell_at(&self, pos: (f32, f32)) -> (i32, i32) {
+        let size = self.voxel_size as f32;
+        ((pos.0 / size).floor() as i32, (pos.1 / size).floor() as i32)
+    }
+
+    // Cursor in world pixels, if it's over this view
+    fn cursor_pos(&self, view: &View) -> Option<(f32, f32)> {
+        self.cursor
+            .and_then(|c| view.viewport.local(c))
+            .map(|c| view.camera.screen_to_world(c))
+    }
+
+    // Cell that a click in the first view would edit. The first player can't
+    // reach through walls: aiming at a voxel picks the first one the ray from
+    // them hits, and aiming at an empty cell picks the last empty one the ray
+    // gets to, right in front of anything in the way.
+    fn target(&self, view: &View) -> Option<(i32, i32)> {
+        let cursor = self.cursor_pos(view)?;
+        let cell = self.cell_at(cursor);
+        let origin = (self.players[0].pos.x, self.players[0].pos.y);
+        let dir = (cursor.0 - origin.0, cursor.1 - origin.1);
+        let Some(hit) = self.raycast(origin, dir, (dir.0 * dir.0 + dir.1 * dir.1).sqrt()) else {
+            return Some(cell);
+        };
+        match hit.cells.len() {
+            // Inside a voxel already, the only one in reach is that one
+            1 => Some(hit.cell),
+            _ if self.is_solid(cell) => Some(hit.cell),
+            n => Some(hit.cells[n - 2]),
+        }
+    }
+
+    // Whether a voxel in `cell` would overlap any player
+    fn blocks_player(&self, cell: (i32, i32)) -> bool {
+        let size = self.voxel_size as f32;
+        let (left, top) = (cell.0 as f32 * size, cell.1 as f32 * size);
+        self.players.iter().any(|player| {
+            // Closest point of the cell to the player's center
+            let dx = player.pos.x - player.pos.x.clamp(left, left + size);
+            let dy = player.pos.y - player.pos.y.clamp(top, top + size);
+            let radius = player.size() as f32;
+            dx * dx + dy * dy < radius * radius
+        })
+    }
+
+    // Finds the first solid voxel along a ray, see raycast::raycast
+    fn raycast(&self, origin: (f32, f32), dir: (f32, f32), max_dist: f32) -> Option<RayHit> {
+        raycast::raycast(|cell| self.is_solid(cell), self.voxel_size as f32, origin, dir, max_dist)
+    }
+
+    // Copies a generated layout over the voxels so that its start cell ends
+    // up on `cell`
+    fn stamp(&mut self, layout: &Layout, cell: (i32, i32)) {
+        let left = cell.0 - layout.start.0;
+        let top = cell.1 - layout.start.1;
+        for y in 0..layout.height {
+            for x in 0..layout.width {
+                if layout.is_solid(x, y) {
+                    self.voxels.insert((left + x, top + y));
+                } else {
+                    self.voxels.remove(&(left + x, top + y));
+                }
+            }
+        }
+    }
+
+    // Swaps the world for the one described by a scene file
+    fn load_scene(&mut self, scene: &Scene) {
+        self.voxels = scene.voxels.clone();
+        self.triggers = scene.triggers.clone();
+        self.inside.clear();
+
+        // Start in the middle of the player's cell with the camera already
+        // on them, rather than easing over from wherever it was
+        let size = self.voxel_size as f32;
+        let (x, y) = ((scene.player.0 as f32 + 0.5) * size, (scene.player.1 as f32 + 0.5) * size);
+        self.players[0].pos = Vec3 { x, y, z: 0.0 };
+        self.views[0].camera.pos = (x, y);
+    }
+
+    // Replaces the world with the seeded dungeon the benchmark flies around
+    fn start_benchmark(&mut self) -> Benchmark {
+        let layout = benchmark::dungeon();
+        let (width, height) = (layout.width, layout.height);
+        self.voxels.clear();
+        self.stamp(&layout, (0, 0));
+
+        let size = self.voxel_size as f32;
+        let center = (
+            ((width / 2 - layout.start.0) as f32) * size,
+            ((height / 2 - layout.start.1) as f32) * size,
+        );
+        self.flock.spawn(center, benchmark::BIRDS);
+        Benchmark::new(center, (width as f32 * size * 0.35, height as f32 * size * 0.35))
+    }
+
+    // Adds a second player next to the first one, or removes them again, and
+    // splits the screen between everyone who is playing
+    fn toggle_second_player(&mut self) {
+        if self.players.len() > 1 {
+            self.players.truncate(1);
+            self.views.truncate(1);
+            self.inside.retain(|&(_, p)| p == 0);
+        } else {
+            let pos = (self.players[0].pos.x + 2.0 * self.voxel_size as f32, self.players[0].pos.y);
+            self.players.push(Player::new(pos, ARROWS));
+            self.views.push(View {
+                camera: Camera::new(pos, WIDTH, HEIGHT),
+                viewport: Viewport { x: 0, y: 0, width: WIDTH, height: HEIGHT },
+            });
+        }
+
+        // Side by side columns of equal width
+        let width = WIDTH / self.views.len() as u32;
+        for (i, view) in self.views.iter_mut().enumerate() {
+            view.viewport = Viewport { x: i as u32 * width, y: 0, width, height: HEIGHT };
+            view.camera.resize(width, HEIGHT);
+        }
+    }
+
+    // Advances the simulation by one fixed tick, moving every player with the
+    // keys that are currently held down.
+    // `key_held` tells which keys are down, normally straight from the
+    // keyboard but a scene can script some of them
+    fn tick(&mut self, key_held: impl Fn(KeyCode) -> bool) {
+        for player in &mut self.players {
+            let controls = &player.controls;
+            let moving = [controls.up, controls.down, controls.left, controls.right]
+                .into_iter()
+                .any(&key_held);
+
+            // Crouching wins if both are held, and sprinting takes stamina
+            player.stance = if key_held(controls.crouch) {
+                Stance::Crouching
+            } else if key_held(controls.sprint) && moving && !player.winded {
+                Stance::Sprinting
+            } else {
+                Stance::Walking
+            };
+            let speed = player.speed();
+
+            // Move up: decrease y
+            if key_held(player.controls.up) {
+                player.pos.y -= speed;
+            }
+            // Move down: increase y
+            if key_held(player.controls.down) {
+                player.pos.y += speed;
+            }
+            // Move left: decrease x
+            if key_held(player.controls.left) {
+                player.pos.x -= speed;
+            }
+            // Move right: increase x
+            if key_held(player.controls.right) {
+                player.pos.x += speed;
+            }
+            player.update_stamina(moving);
+        }
+        self.check_triggers();
+
+        // Birds circle around the first player
+        let home = (self.players[0].pos.x, self.players[0].pos.y);
+        self.flock.tick(home, |cell| self.voxels.contains(&cell), self.voxel_size as f32);
+    }
+
+    // Fires the triggers that players walked into this tick. Teleports happen
+    // right away, the rest is left in `fired` for the main loop.
+    fn check_triggers(&mut self) {
+        let size = self.voxel_size as f32;
+
+        for p in 0..self.players.len() {
+            let cell = self.cell_at((self.players[p].pos.x, self.players[p].pos.y));
+            for (t, trigger) in self.triggers.iter().enumerate() {
+                if !trigger.contains(cell) {
+                    self.inside.remove(&(t, p));
+                    continue;
+                }
+                // Only entering counts, standing inside doesn't fire again
+                if !self.inside.insert((t, p)) {
+                    continue;
+                }
+                match &trigger.action {
+                    // The player isn't in `cell` anymore, so the rest of the
+                    // triggers wait for the next tick to see where they landed
+                    Action::Teleport((x, y)) => {
+                        self.players[p].pos.x = (*x as f32 + 0.5) * size;
+                        self.players[p].pos.y = (*y as f32 + 0.5) * size;
+                        break;
+                    }
+                    action => self.fired.push(action.clone()),
+                }
+            }
+        }
+    }
+
+    // Handles the input that isn't part of the simulation, it runs once per
+    // frame even when the clock is paused. The cursor is in frame pixels.
+    fn handle_input(&mut self, input: &WinitInputHelper, cursor: Option<(f32, f32)>) {
+        self.cursor = cursor;
+
+        // Grid overlay: G toggles it, [ and ] halve or double the cell size,
+        // N cycles the subdivisions and 0 snaps the cells back to the voxels
+        if input.key_pressed(KeyCode::KeyG) {
+            self.grid.visible = !self.grid.visible;
+        }
+        if input.key_pressed(KeyCode::BracketLeft) {
+            self.grid.cell_size = (self.grid.cell_size / 2.0).max(settings::MIN_GRID_CELL_SIZE);
+        }
+        if input.key_pressed(KeyCode::BracketRight) {
+            self.grid.cell_size = (self.grid.cell_size * 2.0).min(settings::MAX_GRID_CELL_SIZE);
+        }
+        if input.key_pressed(KeyCode::KeyN) {
+            self.grid.subdivisions = match self.grid.subdivisions {
+                1 => 2,
+                2 => 4,
+                _ => 1,
+            };
+        }
+        if input.key_pressed(KeyCode::Digit0) {
+            self.grid.cell_size = self.voxel_size as f32;
+        }
+
+        // Toggle leaning the camera towards the cursor
+        if input.key_pressed(KeyCode::KeyL) {
+            let camera = &mut self.views[0].camera;
+            camera.look_ahead = match camera.look_ahead {
+                Some(_) => None,
+                None => Some(camera::LOOK_AHEAD),
+            };
+        }
+
+        // Toggle dimming what the players can't see
+        if input.key_pressed(KeyCode::KeyV) {
+            self.fov_enabled = !self.fov_enabled;
+        }
+
+        // Left click fills the targeted empty cell, unless a player is in the
+        // way. Holding it on a voxel breaks it after BREAK_TIME, letting go or
+        // aiming away from the voxel before then starts over.
+        let dt = input.delta_time().unwrap_or_default().as_secs_f32();
+        let target = self.target(&self.views[0]);
+        if input.mouse_pressed(0) && let Some(cell) = target {
+            if self.is_solid(cell) {
+                self.breaking = Some((cell, 0.0));
+            } else if !self.blocks_player(cell) {
+                self.voxels.insert(cell);
+            }
+        }
+        if let Some((cell, progress)) = self.breaking {
+            let progress = progress + dt / BREAK_TIME;
+            self.breaking = if !input.mouse_held(0) || target != Some(cell) || !self.is_solid(cell) {
+                None
+            } else if progress >= 1.0 {
+                self.voxels.remove(&cell);
+                None
+            } else {
+                Some((cell, progress))
+            };
+        }
+
+        // Shapes between two cells: Q drops an anchor under the cursor, or
+        // removes it when it's already there. Then E builds a wall from the
+        // anchor to the cursor, X a rectangle with them as corners and O a
+        // circle around the anchor out to the cursor. Holding Alt fills
+        // rectangles and circles in.
+        let cursor = self.cursor_pos(&self.views[0]).map(|pos| self.cell_at(pos));
+        if input.key_pressed(KeyCode::KeyQ) {
+            self.anchor = if self.anchor == cursor { None } else { cursor };
+        }
+        if let (Some(anchor), Some(cell)) = (self.anchor, cursor) {
+            let filled = input.held_alt();
+            let shape = if input.key_pressed(KeyCode::KeyE) {
+                shapes::line(anchor, cell)
+            } else if input.key_pressed(KeyCode::KeyX) {
+                shapes::rectangle(anchor, cell, filled)
+            } else if input.key_pressed(KeyCode::KeyO) {
+                shapes::circle(anchor, cell, filled)
+            } else {
+                Vec::new()
+            };
+            self.voxels.extend(shape);
+        }
+
+        // M stamps a maze around the player, Alt+M a dungeon
+        if input.key_pressed(KeyCode::KeyM) {
+            let mut rng = Rng::from_time();
+            let layout = if input.held_alt() {
+                generate::dungeon(48, 36, &mut rng)
+            } else {
+                generate::maze(21, 21, &mut rng)
+            };
+            let cell = self.cell_at((self.players[0].pos.x, self.players[0].pos.y));
+            self.stamp(&layout, cell);
+        }
+
+        // R stamps ruins synthesized from the example template
+        if input.key_pressed(KeyCode::KeyR) {
+            let mut rng = Rng::from_time();
+            match wfc::synthesize(&wfc::RUINS, 3, 32, 24, &mut rng) {
+                Some(layout) => {
+                    let cell = self.cell_at((self.players[0].pos.x, self.players[0].pos.y));
+                    self.stamp(&layout, cell);
+                }
+                None => eprintln!("Wave function collapse failed to place the ruins"),
+            }
+        }
+
+        // Release more birds, handy for stress testing the tick
+        if input.key_pressed(KeyCode::KeyB) {
+            let pos = (self.players[0].pos.x, self.players[0].pos.y);
+            self.flock.spawn(pos, 50);
+        }
+
+        // Second local player joins or leaves, steering with the arrow keys
+        if input.key_pressed(KeyCode::F2) {
+            self.toggle_second_player();
+        }
+
+        for (i, (view, player)) in self.views.iter_mut().zip(&self.players).enumerate() {
+            let cursor = match i {
+                0 => self.cursor
+                    .and_then(|c| view.viewport.local(c))
+                    .map(|c| view.camera.screen_to_world(c)),
+                _ => None,
+            };
+            view.camera.follow((player.pos.x, player.pos.y), cursor, dt);
         }
     }
     
 }
 
+struct Args {
+    scene: Option<Scene>,
+    benchmark: bool,
+}
+
+// `--scene <file>` starts in a scene instead of the default world and
+// `--benchmark` runs the benchmark in benchmark.rs
+fn parse_args() -> Args {
+    let mut args = std::env::args().skip(1);
+    let mut parsed = Args { scene: None, benchmark: false };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--benchmark" => parsed.benchmark = true,
+            "--scene" => {
+                let Some(path) = args.next() else {
+                    eprintln!("--scene needs a file");
+                    process::exit(2);
+                };
+                match Scene::load(&path) {
+                    Ok(scene) => parsed.scene = Some(scene),
+                    Err(err) => {
+                        eprintln!("Couldn't load scene {}: {}", path, err);
+                        process::exit(1);
+                    }
+                }
+            }
+            _ => {
+                eprintln!("Unknown argument `{}`", arg);
+                process::exit(2);
+            }
+        }
+    }
+    parsed
+}
+
+// Moves the gamma by a number of steps. It snaps to whole steps so going up
+// and back down lands exactly on 1.0 again.
+fn nudge_gamma(post: &mut PostProcess, steps: f32) {
+    let gamma = ((post.gamma() / GAMMA_STEP).round() + steps) * GAMMA_STEP;
+    post.set_gamma(gamma.clamp(MIN_GAMMA, MAX_GAMMA));
+}
+
+// Show the engine state in the title since there is no text rendering
+fn window_title(
+    clock: &Clock,
+    recorder: &Recorder,
+    theme: &Theme,
+    post: &PostProcess,
+    path: &CameraPath,
+    message: Option<&str>,
+) -> String {
+    let mut status = Vec::new();
+    if let Some(message) = message {
+        status.push(message.to_string());
+    }
+    if theme.name != "default" {
+        status.push(theme.name.clone());
+    }
+    if post.gamma() != 1.0 {
+        status.push(format!("gamma {:.1}", post.gamma()));
+    }
+    if !clock.status().is_empty() {
+        status.push(clock.status());
+    }
+    if recorder.is_recording() {
+        status.push("recording".to_string());
+    }
+    if path.is_playing() {
+        status.push("playing path".to_string());
+    } else if path.len() > 0 {
+        status.push(format!("{} keyframes", path.len()));
+    }
+
+    if status.is_empty() {
+        TITLE.to_string()
+    } else {
+        format!("{} [{}]", TITLE, status.join(", "))
+    }
+}
+
 fn main() {
+    crash::install();
+    let args = parse_args();
+
     let event_loop = EventLoop::new().unwrap();
     let mut input = WinitInputHelper::new();
 
+    // Benchmarks run with the default settings so results stay comparable
+    let settings = if args.benchmark {
+        Settings::new(WIDTH, HEIGHT)
+    } else {
+        Settings::load(WIDTH, HEIGHT)
+    };
+
     let window = {
-        let size = LogicalSize::new(WIDTH as f64, HEIGHT as f64);
+        let min_size = LogicalSize::new(WIDTH as f64, HEIGHT as f64);
+        let (width, height) = settings.window_size;
         WindowBuilder::new()
-            .with_title("Tiny Voxel Engine")
-            .with_inner_size(size)
-            .with_min_inner_size(size)
+            .with_title(TITLE)
+            .with_inner_size(LogicalSize::new(width as f64, height as f64))
+            .with_min_inner_size(min_size)
             .build(&event_loop)
             .unwrap()
     };
@@ -166,11 +1033,76 @@ fn main() {
         Pixels::new(WIDTH, HEIGHT, surface_texture)
     }.unwrap();
 
+    // Benchmarks only use the built-in themes so a user's files can't change
+    // what gets drawn
+    let mut themes = if args.benchmark { Themes::built_in() } else { Themes::load() };
+    if !settings.theme.is_empty() && !themes.select(&settings.theme) {
+        eprintln!("Theme `{}` from the settings doesn't exist", settings.theme);
+    }
+
     let mut world = World{
         voxel_size: 40,
-        player: Player { pos: Vec3{x: 0.0, y: 0.0, z: 0.0}, radius: 10 },
+        // A short wall to the right of the spawn to cast rays against
+        voxels: (-2..=2).map(|y| (3, y)).collect(),
+        players: vec![Player::new((0.0, 0.0), WASD)],
+        views: vec![View {
+            camera: Camera::new((0.0, 0.0), WIDTH, HEIGHT),
+            viewport: Viewport { x: 0, y: 0, width: WIDTH, height: HEIGHT },
+        }],
+        flock: Flock::new(),
+        grid: Grid {
+            visible: settings.grid_visible,
+            cell_size: settings.grid_cell_size,
+            subdivisions: settings.grid_subdivisions,
+        },
+        fov_enabled: settings.fov_enabled,
+        theme: themes.current().clone(),
         cursor: None,
+        breaking: None,
+        anchor: None,
+        triggers: Vec::new(),
+        inside: HashSet::new(),
+        fired: Vec::new(),
     };
+    if let Some(scene) = &args.scene {
+        world.load_scene(scene);
+    }
+    if !settings.look_ahead {
+        world.views[0].camera.look_ahead = None;
+    }
+    let mut benchmark = args.benchmark.then(|| world.start_benchmark());
+    if benchmark.is_none() {
+        world.flock.spawn((0.0, 0.0), 30);
+    }
+
+    let mut clock = Clock::new();
+    let mut profiler = Profiler::new();
+    let mut recorder = Recorder::new(WIDTH, HEIGHT);
+    let mut title = TITLE.to_string();
+    let mut ticks: u64 = 0;
+    let mut path = CameraPath::new();
+    let mut map_export = MapExport::new();
+    // Latest message from a trigger and when it was shown
+    let mut message: Option<(String, Instant)> = None;
+    // Whether the recording was started by playing the camera path
+    let mut recording_path = false;
+
+    let mut post = PostProcess::new(WIDTH, HEIGHT, vec![
+        Effect::Bloom { threshold: 0.5, intensity: 1.5, radius: 4 },
+        Effect::Vignette { strength: 0.4 },
+        Effect::Tonemap { white: 1.5 },
+    ]);
+    post.enabled = settings.post_processing;
+    post.set_gamma(settings.gamma.clamp(MIN_GAMMA, MAX_GAMMA));
+    if settings.antialiasing {
+        post.toggle(FXAA);
+    }
+    if settings.crt {
+        post.toggle(CRT);
+    }
+    if settings.dithering {
+        post.toggle(Effect::dither(&world.theme.dither_palette(), DITHER_SPREAD));
+    }
 
     event_loop.run(|event, elwt| {
 
@@ -179,19 +1111,188 @@ fn main() {
             ..
         } = event
         {
-            world.draw(pixels.frame_mut());
-            pixels.render().unwrap();
+            if let Some(benchmark) = &benchmark {
+                let (x, y) = benchmark.camera_pos();
+                world.players[0].pos = Vec3 { x, y, z: 0.0 };
+                world.views[0].camera.pos = (x, y);
+            }
+            let started = Instant::now();
+            {
+                puffin::profile_scope!("draw");
+                world.draw(pixels.frame_mut());
+            }
+            {
+                puffin::profile_scope!("post");
+                post.apply(pixels.frame_mut());
+            }
+            let frame_time = started.elapsed();
+            recorder.capture(pixels.frame());
+            {
+                puffin::profile_scope!("present");
+                pixels.render().unwrap();
+            }
+            profiler.end_frame();
+
+            if let Some(benchmark) = &mut benchmark {
+                if benchmark.record(frame_time) {
+                    benchmark.finish();
+                    elwt.exit();
+                    return;
+                }
+                // Exactly one tick per measured frame, so every run simulates
+                // the same frames however fast they are drawn
+                world.tick(|_| false);
+            }
         }
 
         if input.update(&event) {
             if input.key_pressed(KeyCode::Escape) || input.close_requested() {
+                let size = window.inner_size().to_logical::<u32>(window.scale_factor());
+                let settings = Settings {
+                    window_size: (size.width, size.height),
+                    theme: world.theme.name.clone(),
+                    post_processing: post.enabled,
+                    antialiasing: post.contains(&FXAA),
+                    gamma: post.gamma(),
+                    crt: post.contains(&CRT),
+                    dithering: post.effects.iter().any(|e| matches!(e, Effect::Dither { .. })),
+                    grid_visible: world.grid.visible,
+                    grid_cell_size: world.grid.cell_size,
+                    grid_subdivisions: world.grid.subdivisions,
+                    fov_enabled: world.fov_enabled,
+                    look_ahead: world.views[0].camera.look_ahead.is_some(),
+                };
+                if !args.benchmark {
+                    settings.save();
+                }
                 elwt.exit();
                 return;
             }
             if let Some(size) = input.window_resized() {
                 pixels.resize_surface(size.width, size.height).unwrap();
             }
-            world.handle_input(&input);
+            // Benchmarks ignore every other key, and tick when frames are drawn
+            if benchmark.is_some() {
+                window.request_redraw();
+                return;
+            }
+            {
+                puffin::profile_scope!("input");
+                // Toggle the post-processing chain
+                if input.key_pressed(KeyCode::KeyP) {
+                    post.enabled = !post.enabled;
+                }
+                // - and = darken or brighten the shadows by adjusting the gamma
+                if input.key_pressed(KeyCode::Minus) {
+                    nudge_gamma(&mut post, -1.0);
+                }
+                if input.key_pressed(KeyCode::Equal) {
+                    nudge_gamma(&mut post, 1.0);
+                }
+                // Toggle anti-aliasing
+                if input.key_pressed(KeyCode::KeyF) {
+                    post.toggle(FXAA);
+                }
+                // Toggle the CRT filter
+                if input.key_pressed(KeyCode::KeyC) {
+                    post.toggle(CRT);
+                }
+                // Toggle the retro look, dithered down to the theme's palette
+                if input.key_pressed(KeyCode::KeyK) {
+                    post.toggle(Effect::dither(&world.theme.dither_palette(), DITHER_SPREAD));
+                }
+                // Switch to the next color theme
+                if input.key_pressed(KeyCode::KeyT) {
+                    world.theme = themes.cycle().clone();
+                    post.replace(Effect::dither(&world.theme.dither_palette(), DITHER_SPREAD));
+                }
+                // Start or stop recording profiling scopes
+                if input.key_pressed(KeyCode::F9) {
+                    profiler.toggle();
+                }
+
+                // Start or stop recording a GIF of what is on screen
+                if input.key_pressed(KeyCode::F10) {
+                    recorder.toggle();
+                }
+                // Photo mode: J drops a camera keyframe and Alt+J clears them,
+                // U plays the path back and Alt+U records a GIF of it too.
+                // Alt rather than Shift, which is already sprint.
+                if input.key_pressed(KeyCode::KeyJ) {
+                    if input.held_alt() {
+                        path.clear();
+                    } else {
+                        path.add(world.views[0].camera.pos);
+                    }
+                }
+                if input.key_pressed(KeyCode::KeyU) {
+                    if path.is_playing() {
+                        path.stop();
+                    } else if path.play() && input.held_alt() && !recorder.is_recording() {
+                        recorder.toggle();
+                        recording_path = true;
+                    }
+                }
+                // Save a map of the whole world, Alt+F12 cuts it into tiles
+                // for a web viewer
+                if input.key_pressed(KeyCode::F12) {
+                    let players = world.players.iter().map(|p| world.cell_at((p.pos.x, p.pos.y))).collect();
+                    map_export.start(world.voxels.clone(), players, world.theme.clone(), input.held_alt());
+                }
+                clock.handle_input(&input);
+            }
+
+            let shown = message.as_ref()
+                .filter(|(_, since)| since.elapsed().as_secs_f32() < MESSAGE_TIME)
+                .map(|(text, _)| text.as_str());
+            let new_title = window_title(&clock, &recorder, &world.theme, &post, &path, shown);
+            if new_title != title {
+                window.set_title(&new_title);
+                title = new_title;
+            }
+            let frame_dt = input.delta_time().unwrap_or_default().as_secs_f32();
+            for _ in 0..clock.advance(frame_dt) {
+                puffin::profile_scope!("tick");
+                let scripted = |key| args.scene.as_ref().is_some_and(|s| s.key_held(key, ticks));
+                world.tick(|key| input.key_held(key) || scripted(key));
+                ticks += 1;
+            }
+            for action in world.fired.drain(..) {
+                match action {
+                    Action::Message(text) => message = Some((text, Instant::now())),
+                    Action::Theme(name) => {
+                        if themes.select(&name) {
+                            world.theme = themes.current().clone();
+                            post.replace(Effect::dither(&world.theme.dither_palette(), DITHER_SPREAD));
+                        } else {
+                            eprintln!("Trigger wants theme `{}` which doesn't exist", name);
+                        }
+                    }
+                    Action::Teleport(_) => {}
+                }
+            }
+            // The cursor comes in physical window pixels, which only match
+            // the frame when the scale factor is 1 and the window hasn't been
+            // resized, so let pixels map it through its scaling
+            let cursor = input.cursor()
+                .and_then(|pos| pixels.window_pos_to_pixel(pos).ok())
+                .map(|(x, y)| (x as f32, y as f32));
+            {
+                puffin::profile_scope!("input");
+                world.handle_input(&input, cursor);
+            }
+
+            // A playing path takes the camera away from the player
+            if let Some(pos) = path.advance(frame_dt) {
+                world.views[0].camera.pos = pos;
+            } else if recording_path {
+                // Unless it was already stopped by hand
+                if recorder.is_recording() {
+                    recorder.toggle();
+                }
+                recording_path = false;
+            }
+
             window.request_redraw();
         }
 
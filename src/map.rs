@@ -0,0 +1,366 @@
+// Exports a top-down map of the whole world to map-<timestamp>.png. The map
+// covers every voxel and player with a little empty space around them, each
+// cell drawn as a small square in the colors of the current theme. Drawing
+// and compressing a big world takes a while, so it happens on a worker thread
+// with its own copy of the cells. Neither kind of export holds the whole
+// image in memory: the single PNG is streamed out a row at a time, and tiles
+// are drawn straight from the cells and shrunk into the zoom levels above
+// them as they go.
+//
+// The map can also be cut into a pyramid of 256x256 tiles instead, laid out
+// as map-<timestamp>/<zoom>/<x>/<y>.png the way web map viewers expect, with
+// an index.html that browses them using Leaflet.
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::thread::{self, JoinHandle};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::color;
+use crate::theme::Theme;
+
+// Size of a cell on the map, shrunk for worlds that would get too big
+const CELL_PIXELS: u32 = 4;
+const MAX_SIZE: u32 = 16384;
+// Empty cells left around the edge of the world
+const BORDER: i32 = 2;
+const TILE_SIZE: u32 = 256;
+
+struct Image {
+    width: u32,
+    height: u32,
+    rgba: Vec<u8>,
+}
+
+// The map at full size, drawn on demand from the cells
+struct Map {
+    voxels: HashSet<(i32, i32)>,
+    players: HashMap<(i32, i32), [u8; 4]>,
+    // Cell in the top left corner and pixels per cell
+    min: (i32, i32),
+    scale: u32,
+    width: u32,
+    height: u32,
+    voxel: [u8; 4],
+    background: [u8; 4],
+}
+
+pub struct MapExport {
+    // Exports that may still be writing their file
+    writers: Vec<JoinHandle<()>>,
+}
+
+impl MapExport {
+
+    pub fn new() -> Self {
+        Self { writers: Vec::new() }
+    }
+
+    // Starts exporting the solid `voxels` with the players at the given cells,
+    // as one image or as a folder of tiles
+    pub fn start(&mut self, voxels: HashSet<(i32, i32)>, players: Vec<(i32, i32)>, theme: Theme, tiles: bool) {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let path = if tiles { format!("map-{}", timestamp) } else { format!("map-{}.png", timestamp) };
+
+        self.writers.retain(|writer| !writer.is_finished());
+        self.writers.push(thread::spawn(move || {
+            let result = Map::new(voxels, &players, &theme).and_then(|map| {
+                if tiles {
+                    write_tiles(&path, &map)
+                } else {
+                    write_png(&path, map.width, map.height, |y, row| map.row(y, row))
+                }
+            });
+            match result {
+                Ok(()) => eprintln!("Saved the map to {}", path),
+                Err(err) => eprintln!("Failed to save {}: {}", path, err),
+            }
+        }));
+    }
+}
+
+impl Drop for MapExport {
+    fn drop(&mut self) {
+        // Let exports that are still running finish before the engine exits
+        for writer in self.writers.drain(..) {
+            let _ = writer.join();
+        }
+    }
+}
+
+impl Map {
+
+    fn new(voxels: HashSet<(i32, i32)>, players: &[(i32, i32)], theme: &Theme) -> Result<Map, Box<dyn Error>> {
+        let (mut min, mut max) = ((i32::MAX, i32::MAX), (i32::MIN, i32::MIN));
+        for &(x, y) in voxels.iter().chain(players) {
+            min = (min.0.min(x), min.1.min(y));
+            max = (max.0.max(x), max.1.max(y));
+        }
+        let min = (min.0 - BORDER, min.1 - BORDER);
+        let max = (max.0 + BORDER, max.1 + BORDER);
+        let cells_wide = (max.0 - min.0 + 1) as u32;
+        let cells_high = (max.1 - min.1 + 1) as u32;
+
+        let scale = (1..=CELL_PIXELS).rev()
+            .find(|scale| cells_wide * scale <= MAX_SIZE && cells_high * scale <= MAX_SIZE)
+            .ok_or_else(|| format!("the world is too big to map ({} x {} cells)", cells_wide, cells_high))?;
+
+        // Later players are drawn over earlier ones in the same cell
+        let players = players.iter().copied().zip(theme.players.iter().copied()).collect();
+        Ok(Map {
+            voxels,
+            players,
+            min,
+            scale,
+            width: cells_wide * scale,
+            height: cells_high * scale,
+            voxel: theme.voxel,
+            background: theme.background,
+        })
+    }
+
+    // Fills `rgba` with pixels from row `y`, starting at column `left`.
+    // Pixels past the edge of the map get the background color
+    fn fill_row(&self, y: u32, left: u32, rgba: &mut [u8]) {
+        let cell_y = self.min.1 + (y / self.scale) as i32;
+        for (i, pixel) in rgba.chunks_exact_mut(4).enumerate() {
+            let x = left + i as u32;
+            let color = if x >= self.width || y >= self.height {
+                self.background
+            } else {
+                let cell = (self.min.0 + (x / self.scale) as i32, cell_y);
+                match self.players.get(&cell) {
+                    Some(&color) => color,
+                    None if self.voxels.contains(&cell) => self.voxel,
+                    None => self.background,
+                }
+            };
+            pixel.copy_from_slice(&color);
+        }
+    }
+
+    fn row(&self, y: u32, rgba: &mut [u8]) {
+        self.fill_row(y, 0, rgba);
+    }
+
+    // One full size tile of the map
+    fn tile(&self, left: u32, top: u32) -> Image {
+        let mut rgba = vec![0; (TILE_SIZE * TILE_SIZE * 4) as usize];
+        for (y, row) in rgba.chunks_exact_mut((TILE_SIZE * 4) as usize).enumerate() {
+            self.fill_row(top + y as u32, left, row);
+        }
+        Image { width: TILE_SIZE, height: TILE_SIZE, rgba }
+    }
+}
+
+// Writes a PNG a row at a time, `row` fills in the pixels of row `y`
+fn write_png(path: &str, width: u32, height: u32, mut row: impl FnMut(u32, &mut [u8])) -> Result<(), Box<dyn Error>> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    let mut stream = writer.stream_writer()?;
+    let mut rgba = vec![0; (width * 4) as usize];
+    for y in 0..height {
+        row(y, &mut rgba);
+        stream.write_all(&rgba)?;
+    }
+    stream.finish()?;
+    Ok(())
+}
+
+// Writes the zoom levels from the full size map down to one that fits in a
+// single tile, each level half the size of the one below
+fn write_tiles(dir: &str, map: &Map) -> Result<(), Box<dyn Error>> {
+    let max_zoom = max_zoom(map.width, map.height);
+    write_tile(dir, map, max_zoom, 0, (0, 0))?;
+    fs::write(format!("{}/index.html", dir), viewer(map.width, map.height, max_zoom, map.background))?;
+    Ok(())
+}
+
+// Zoom level at which the map is drawn at full size, zoom 0 fits in a tile
+fn max_zoom(width: u32, height: u32) -> u32 {
+    let mut zoom = 0;
+    while TILE_SIZE << zoom < width.max(height) {
+        zoom += 1;
+    }
+    zoom
+}
+
+// Writes tile `(x, y)` of level `zoom` along with the tiles under it at the
+// higher zoom levels, and returns it so the level above can shrink it. Only
+// one tile per level is waiting on its children at a time.
+fn write_tile(dir: &str, map: &Map, max_zoom: u32, zoom: u32, (x, y): (u32, u32)) -> Result<Image, Box<dyn Error>> {
+    let tile = if zoom == max_zoom {
+        map.tile(x * TILE_SIZE, y * TILE_SIZE)
+    } else {
+        // Each level halves the size of the one below it, rounding up
+        let shrink = 1 << (max_zoom - zoom - 1);
+        let (width, height) = (map.width.div_ceil(shrink), map.height.div_ceil(shrink));
+
+        let mut children = Image {
+            width: TILE_SIZE * 2,
+            height: TILE_SIZE * 2,
+            rgba: map.background.repeat((TILE_SIZE * TILE_SIZE * 4) as usize),
+        };
+        for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let child = (x * 2 + dx, y * 2 + dy);
+            if child.0 * TILE_SIZE >= width || child.1 * TILE_SIZE >= height {
+                continue;
+            }
+            let tile = write_tile(dir, map, max_zoom, zoom + 1, child)?;
+            paste(&mut children, &tile, dx * TILE_SIZE, dy * TILE_SIZE);
+        }
+        half_size(&children)
+    };
+
+    let column = format!("{}/{}/{}", dir, zoom, x);
+    fs::create_dir_all(&column)?;
+    let row_bytes = (tile.width * 4) as usize;
+    write_png(&format!("{}/{}.png", column, y), tile.width, tile.height, |row, rgba| {
+        let from = row as usize * row_bytes;
+        rgba.copy_from_slice(&tile.rgba[from..from + row_bytes]);
+    })?;
+    Ok(tile)
+}
+
+// Copies `image` into `into` with its top left corner at `(left, top)`
+fn paste(into: &mut Image, image: &Image, left: u32, top: u32) {
+    let row_bytes = (image.width * 4) as usize;
+    for y in 0..image.height {
+        let from = (y * image.width * 4) as usize;
+        let to = (((top + y) * into.width + left) * 4) as usize;
+        into.rgba[to..to + row_bytes].copy_from_slice(&image.rgba[from..from + row_bytes]);
+    }
+}
+
+// Averages every 2x2 block of pixels, in linear light so thin walls fade
+// the way they would when seen from further away
+fn half_size(image: &Image) -> Image {
+    let width = image.width.div_ceil(2);
+    let height = image.height.div_ceil(2);
+    let mut rgba = Vec::with_capacity((width * height * 4) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let mut sum = [0.0; 3];
+            let mut count = 0.0;
+            for sy in y * 2..(y * 2 + 2).min(image.height) {
+                for sx in x * 2..(x * 2 + 2).min(image.width) {
+                    let i = ((sy * image.width + sx) * 4) as usize;
+                    for (c, total) in sum.iter_mut().enumerate() {
+                        *total += color::srgb_to_linear(image.rgba[i + c]);
+                    }
+                    count += 1.0;
+                }
+            }
+            for total in sum {
+                rgba.push((color::linear_to_srgb(total / count) * 255.0).round() as u8);
+            }
+            rgba.push(0xff);
+        }
+    }
+    Image { width, height, rgba }
+}
+
+// Web page that shows the tiles with Leaflet, served from the tile folder
+fn viewer(width: u32, height: u32, max_zoom: u32, background: [u8; 4]) -> String {
+    format!(r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>World map</title>
+<link rel="stylesheet" href="https://unpkg.com/leaflet@1.9.4/dist/leaflet.css">
+<script src="https://unpkg.com/leaflet@1.9.4/dist/leaflet.js"></script>
+<style>html, body, #map {{ height: 100%; margin: 0; background: #{:02x}{:02x}{:02x}; }}</style>
+</head>
+<body>
+<div id="map"></div>
+<script>
+const maxZoom = {};
+const map = L.map("map", {{ crs: L.CRS.Simple, minZoom: 0, maxZoom: maxZoom + 2 }});
+const bounds = L.latLngBounds(map.unproject([0, 0], maxZoom), map.unproject([{}, {}], maxZoom));
+L.tileLayer("{{z}}/{{x}}/{{y}}.png", {{ maxNativeZoom: maxZoom, bounds: bounds, noWrap: true }}).addTo(map);
+map.fitBounds(bounds);
+</script>
+</body>
+</html>
+"#, background[0], background[1], background[2], max_zoom, width, height)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::theme::Themes;
+
+    fn pixel(image: &Image, x: u32, y: u32) -> [u8; 4] {
+        let i = ((y * image.width + x) * 4) as usize;
+        image.rgba[i..i + 4].try_into().unwrap()
+    }
+
+    #[test]
+    fn max_zoom_fits_the_map_in_one_tile() {
+        assert_eq!(max_zoom(1, 1), 0);
+        assert_eq!(max_zoom(256, 100), 0);
+        assert_eq!(max_zoom(257, 100), 1);
+        assert_eq!(max_zoom(100, 512), 1);
+        assert_eq!(max_zoom(513, 513), 2);
+        assert_eq!(max_zoom(16384, 300), 6);
+    }
+
+    #[test]
+    fn half_size_averages_in_linear_light() {
+        let (black, white) = ([0, 0, 0, 255], [255, 255, 255, 255]);
+        let image = Image {
+            width: 3,
+            height: 2,
+            rgba: [black, white, white, white, black, white].concat(),
+        };
+        let half = half_size(&image);
+        assert_eq!((half.width, half.height), (2, 1));
+        // Half the light, which is much brighter than 128 in sRGB
+        assert_eq!(pixel(&half, 0, 0), [188, 188, 188, 255]);
+        // The odd column is averaged on its own
+        assert_eq!(pixel(&half, 1, 0), white);
+    }
+
+    #[test]
+    fn tiles_are_cut_from_the_map() {
+        let theme = Themes::built_in().current().clone();
+        let voxels = [(0, 0), (100, 0)].into_iter().collect();
+        let map = Map::new(voxels, &[(1, 0)], &theme).unwrap();
+        // Cells left of the first voxel are border
+        let left = BORDER as u32 * CELL_PIXELS;
+        assert_eq!(map.width, (101 + 2 * BORDER as u32) * CELL_PIXELS);
+        assert_eq!(map.height, (1 + 2 * BORDER as u32) * CELL_PIXELS);
+
+        let tile = map.tile(0, 0);
+        assert_eq!((tile.width, tile.height), (TILE_SIZE, TILE_SIZE));
+        assert_eq!(pixel(&tile, left - 1, left), theme.background);
+        assert_eq!(pixel(&tile, left, left), theme.voxel);
+        assert_eq!(pixel(&tile, left + CELL_PIXELS, left), theme.players[0]);
+        // Past the bottom of the map
+        assert_eq!(pixel(&tile, left, map.height), theme.background);
+
+        // The second tile starts partway into the map and runs past its edge
+        let tile = map.tile(TILE_SIZE, 0);
+        let x = left + 100 * CELL_PIXELS - TILE_SIZE;
+        assert_eq!(pixel(&tile, x, left), theme.voxel);
+        assert_eq!(pixel(&tile, map.width - TILE_SIZE, left), theme.background);
+        assert_eq!(pixel(&tile, TILE_SIZE - 1, left), theme.background);
+    }
+
+    #[test]
+    fn big_worlds_use_fewer_pixels_per_cell() {
+        let theme = Themes::built_in().current().clone();
+        let voxels = [(0, 0), (MAX_SIZE as i32 / 2, 0)].into_iter().collect();
+        let map = Map::new(voxels, &[], &theme).unwrap();
+        assert_eq!(map.scale, 1);
+        assert!(map.width <= MAX_SIZE);
+
+        let voxels = [(0, 0), (MAX_SIZE as i32, 0)].into_iter().collect();
+        assert!(Map::new(voxels, &[], &theme).is_err());
+    }
+}
@@ -0,0 +1,475 @@
+// Post-processing stage that runs on the finished frame before it is presented.
+//
+// The frame is decoded into a floating point buffer of linear light so effects
+// can push values above 1.0 (bloom adds light on top of what is already there)
+// and blur and darken the way light really does. The last of the HDR effects
+// is expected to be a tonemap which squeezes that range back into something
+// the 8 bit frame buffer can hold. On the way out the values are adjusted by
+// the user's gamma and encoded back to sRGB.
+
+use crate::color;
+
+// Entries in the table used to encode linear values back to sRGB
+const ENCODE_STEPS: usize = 4096;
+
+pub enum Effect {
+    // Bright parts of the frame bleed light into their surroundings
+    Bloom { threshold: f32, intensity: f32, radius: usize },
+    // Darkens the corners of the screen, strength 0.0 disables it
+    Vignette { strength: f32 },
+    // Extended Reinhard, everything at or above `white` maps to full brightness
+    Tonemap { white: f32 },
+    // Smooths jagged edges, it works on displayable colors so it belongs after
+    // the tonemap. `span` is how far in pixels it may blend along an edge.
+    Fxaa { span: f32 },
+    // Snaps every pixel to the nearest palette color, offset by a 4x4 Bayer
+    // matrix first so gradients turn into patterns instead of bands. `spread`
+    // is how far the offset can push a channel.
+    Dither { palette: Vec<[f32; 3]>, spread: f32 },
+    // Old CRT monitor: the picture bulges out by `curvature`, every other
+    // line is darkened by `scanlines` and the red and blue channels drift
+    // `aberration` pixels apart towards the edges
+    Crt { curvature: f32, scanlines: f32, aberration: f32 },
+}
+
+impl Effect {
+
+    // Dither effect for a palette of frame buffer colors
+    pub fn dither(palette: &[[u8; 4]], spread: f32) -> Effect {
+        let palette = palette.iter()
+            .map(|c| [color::srgb_to_linear(c[0]), color::srgb_to_linear(c[1]), color::srgb_to_linear(c[2])])
+            .collect();
+        Effect::Dither { palette, spread }
+    }
+}
+
+pub struct PostProcess {
+    pub enabled: bool,
+    pub effects: Vec<Effect>,
+    width: usize,
+    height: usize,
+    // Exponent applied to linear light before encoding, above 1.0 lifts the
+    // shadows and below 1.0 deepens them
+    gamma: f32,
+    decode: [f32; 256],
+    encode: Vec<u8>,
+    hdr: Vec<[f32; 3]>,
+    scratch: Vec<[f32; 3]>,
+}
+
+impl PostProcess {
+
+    pub fn new(width: u32, height: u32, effects: Vec<Effect>) -> Self {
+        let len = (width * height) as usize;
+        Self {
+            enabled: true,
+            effects,
+            width: width as usize,
+            height: height as usize,
+            gamma: 1.0,
+            decode: std::array::from_fn(|i| color::srgb_to_linear(i as u8)),
+            encode: encode_table(1.0),
+            hdr: vec![[0.0; 3]; len],
+            scratch: vec![[0.0; 3]; len],
+        }
+    }
+
+    pub fn gamma(&self) -> f32 {
+        self.gamma
+    }
+
+    pub fn set_gamma(&mut self, gamma: f32) {
+        self.gamma = gamma;
+        self.encode = encode_table(gamma);
+    }
+
+    // Takes out the effect of the same kind as `effect` if the chain has one,
+    // or adds it to the end otherwise. A dither pass stays last since anything
+    // after it would smear the pattern.
+    pub fn toggle(&mut self, effect: Effect) {
+        match self.position(&effect) {
+            Some(index) => {
+                self.effects.remove(index);
+            }
+            None => {
+                let end = self.effects.iter()
+                    .position(|e| matches!(e, Effect::Dither { .. }))
+                    .unwrap_or(self.effects.len());
+                self.effects.insert(end, effect);
+            }
+        }
+    }
+
+    // Swaps in `effect` for the one of the same kind, if the chain has one
+    pub fn replace(&mut self, effect: Effect) {
+        if let Some(index) = self.position(&effect) {
+            self.effects[index] = effect;
+        }
+    }
+
+    pub fn contains(&self, effect: &Effect) -> bool {
+        self.position(effect).is_some()
+    }
+
+    fn position(&self, effect: &Effect) -> Option<usize> {
+        let kind = std::mem::discriminant(effect);
+        self.effects.iter().position(|e| std::mem::discriminant(e) == kind)
+    }
+
+    pub fn apply(&mut self, frame: &mut [u8]) {
+        // The gamma still applies with the effects turned off
+        let effects: &[Effect] = if self.enabled { &self.effects } else { &[] };
+        if effects.is_empty() && self.gamma == 1.0 {
+            return;
+        }
+
+        for (pixel, hdr) in frame.chunks_exact(4).zip(self.hdr.iter_mut()) {
+            *hdr = [
+                self.decode[pixel[0] as usize],
+                self.decode[pixel[1] as usize],
+                self.decode[pixel[2] as usize],
+            ];
+        }
+
+        for effect in effects {
+            match effect {
+                Effect::Bloom { threshold, intensity, radius } => {
+                    puffin::profile_scope!("bloom");
+                    bloom(&mut self.hdr, &mut self.scratch, self.width, self.height, *threshold, *intensity, *radius);
+                }
+                Effect::Vignette { strength } => {
+                    puffin::profile_scope!("vignette");
+                    vignette(&mut self.hdr, self.width, self.height, *strength);
+                }
+                Effect::Tonemap { white } => {
+                    puffin::profile_scope!("tonemap");
+                    tonemap(&mut self.hdr, *white);
+                }
+                Effect::Fxaa { span } => {
+                    puffin::profile_scope!("fxaa");
+                    fxaa(&self.hdr, &mut self.scratch, self.width, self.height, *span);
+                    std::mem::swap(&mut self.hdr, &mut self.scratch);
+                }
+                Effect::Crt { curvature, scanlines, aberration } => {
+                    puffin::profile_scope!("crt");
+                    crt(&self.hdr, &mut self.scratch, self.width, self.height, *curvature, *scanlines, *aberration);
+                    std::mem::swap(&mut self.hdr, &mut self.scratch);
+                }
+                Effect::Dither { palette, spread } => {
+                    puffin::profile_scope!("dither");
+                    dither(&mut self.hdr, self.width, palette, *spread);
+                }
+            }
+        }
+
+        let last = (ENCODE_STEPS - 1) as f32;
+        let encode = |c: f32| self.encode[(c.clamp(0.0, 1.0) * last).round() as usize];
+        for (pixel, hdr) in frame.chunks_exact_mut(4).zip(self.hdr.iter()) {
+            pixel[0] = encode(hdr[0]);
+            pixel[1] = encode(hdr[1]);
+            pixel[2] = encode(hdr[2]);
+            pixel[3] = 0xff;
+        }
+    }
+}
+
+// Linear light in 0.0..=1.0 to 8 bit sRGB, in ENCODE_STEPS steps
+fn encode_table(gamma: f32) -> Vec<u8> {
+    (0..ENCODE_STEPS)
+        .map(|i| {
+            let linear = (i as f32 / (ENCODE_STEPS - 1) as f32).powf(1.0 / gamma);
+            (color::linear_to_srgb(linear) * 255.0).round() as u8
+        })
+        .collect()
+}
+
+fn luminance(c: [f32; 3]) -> f32 {
+    0.2126 * c[0] + 0.7152 * c[1] + 0.0722 * c[2]
+}
+
+fn bloom(
+    hdr: &mut [[f32; 3]],
+    scratch: &mut [[f32; 3]],
+    width: usize,
+    height: usize,
+    threshold: f32,
+    intensity: f32,
+    radius: usize,
+) {
+    // Bright pass: only keep the part of each pixel that is above the threshold
+    for (src, dst) in hdr.iter().zip(scratch.iter_mut()) {
+        let lum = luminance(*src);
+        *dst = if lum > threshold {
+            let scale = (lum - threshold) / lum;
+            [src[0] * scale, src[1] * scale, src[2] * scale]
+        } else {
+            [0.0; 3]
+        };
+    }
+
+    // A box blur is separable so we blur rows and then columns, each with a
+    // running sum so the cost does not depend on the radius
+    let mut line = vec![[0.0; 3]; width.max(height)];
+    for y in 0..height {
+        let row = &mut scratch[y * width..(y + 1) * width];
+        box_blur_line(row, &mut line[..width], 1, radius);
+    }
+    for x in 0..width {
+        let column = &mut scratch[x..];
+        box_blur_line(column, &mut line[..height], width, radius);
+    }
+
+    for (dst, glow) in hdr.iter_mut().zip(scratch.iter()) {
+        dst[0] += glow[0] * intensity;
+        dst[1] += glow[1] * intensity;
+        dst[2] += glow[2] * intensity;
+    }
+}
+
+// Blurs `out.len()` samples of `data` that are `stride` apart, using `out` as
+// temporary storage before writing the result back in place
+fn box_blur_line(data: &mut [[f32; 3]], out: &mut [[f32; 3]], stride: usize, radius: usize) {
+    let len = out.len();
+    let r = radius as isize;
+    let norm = 1.0 / (2 * radius + 1) as f32;
+    let sample = |data: &[[f32; 3]], i: isize| -> [f32; 3] {
+        if i < 0 || i >= len as isize {
+            [0.0; 3]
+        } else {
+            data[i as usize * stride]
+        }
+    };
+
+    let mut sum = [0.0f32; 3];
+    for i in -r..=r {
+        let s = sample(data, i);
+        sum[0] += s[0];
+        sum[1] += s[1];
+        sum[2] += s[2];
+    }
+
+    for (i, value) in out.iter_mut().enumerate() {
+        *value = [sum[0] * norm, sum[1] * norm, sum[2] * norm];
+        let add = sample(data, i as isize + r + 1);
+        let sub = sample(data, i as isize - r);
+        sum[0] += add[0] - sub[0];
+        sum[1] += add[1] - sub[1];
+        sum[2] += add[2] - sub[2];
+    }
+
+    for (i, value) in out.iter().enumerate() {
+        data[i * stride] = *value;
+    }
+}
+
+fn vignette(hdr: &mut [[f32; 3]], width: usize, height: usize, strength: f32) {
+    let cx = width as f32 / 2.0;
+    let cy = height as f32 / 2.0;
+    // Normalise so the corners are at distance 1.0 from the center
+    let max_dist_sq = cx * cx + cy * cy;
+
+    for (i, pixel) in hdr.iter_mut().enumerate() {
+        let dx = (i % width) as f32 - cx;
+        let dy = (i / width) as f32 - cy;
+        let falloff = 1.0 - strength * (dx * dx + dy * dy) / max_dist_sq;
+        pixel[0] *= falloff;
+        pixel[1] *= falloff;
+        pixel[2] *= falloff;
+    }
+}
+
+fn tonemap(hdr: &mut [[f32; 3]], white: f32) {
+    let white_sq = white * white;
+    for pixel in hdr.iter_mut() {
+        let lum = luminance(*pixel);
+        if lum <= 0.0 {
+            continue;
+        }
+        let mapped = lum * (1.0 + lum / white_sq) / (1.0 + lum);
+        let scale = mapped / lum;
+        pixel[0] *= scale;
+        pixel[1] *= scale;
+        pixel[2] *= scale;
+    }
+}
+
+// Pixel at (x, y), coordinates off the edge are clamped to it
+fn pixel_at(src: &[[f32; 3]], width: usize, height: usize, x: i32, y: i32) -> [f32; 3] {
+    let x = x.clamp(0, width as i32 - 1) as usize;
+    let y = y.clamp(0, height as i32 - 1) as usize;
+    src[y * width + x]
+}
+
+// Bilinear sample at a position in pixels, pixel centers are at .5
+fn sample(src: &[[f32; 3]], width: usize, height: usize, x: f32, y: f32) -> [f32; 3] {
+    let (x, y) = (x - 0.5, y - 0.5);
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let (x0, y0) = (x0 as i32, y0 as i32);
+    let at = |x, y| pixel_at(src, width, height, x, y);
+    let (a, b, c, d) = (at(x0, y0), at(x0 + 1, y0), at(x0, y0 + 1), at(x0 + 1, y0 + 1));
+    std::array::from_fn(|i| {
+        let top = a[i] + (b[i] - a[i]) * fx;
+        let bottom = c[i] + (d[i] - c[i]) * fx;
+        top + (bottom - top) * fy
+    })
+}
+
+// FXAA in the style of Timothy Lottes' original shader. The luma gradient over
+// the four diagonal neighbours gives the direction an edge runs in, and the
+// pixel is replaced by samples taken along that direction. If the wider blend
+// strays outside the luma range of the neighbourhood it crossed the edge, so
+// the narrower one is used instead.
+fn fxaa(src: &[[f32; 3]], dst: &mut [[f32; 3]], width: usize, height: usize, span: f32) {
+    const REDUCE_MIN: f32 = 1.0 / 128.0;
+    const REDUCE_MUL: f32 = 1.0 / 8.0;
+
+    let at = |x: i32, y: i32| pixel_at(src, width, height, x, y);
+    let sample = |x: f32, y: f32| sample(src, width, height, x, y);
+    // Edges are judged on perceived brightness, and the square root is close
+    // enough to the sRGB curve for that
+    let luma = |c: [f32; 3]| luminance(c).sqrt();
+    let mix = |a: [f32; 3], b: [f32; 3]| -> [f32; 3] { std::array::from_fn(|i| (a[i] + b[i]) * 0.5) };
+
+    for y in 0..height as i32 {
+        for x in 0..width as i32 {
+            let nw = luma(at(x - 1, y - 1));
+            let ne = luma(at(x + 1, y - 1));
+            let sw = luma(at(x - 1, y + 1));
+            let se = luma(at(x + 1, y + 1));
+            let m = luma(at(x, y));
+            let luma_min = m.min(nw.min(ne).min(sw.min(se)));
+            let luma_max = m.max(nw.max(ne).max(sw.max(se)));
+
+            let dir = (-((nw + ne) - (sw + se)), (nw + sw) - (ne + se));
+            let reduce = ((nw + ne + sw + se) * 0.25 * REDUCE_MUL).max(REDUCE_MIN);
+            let scale = 1.0 / (dir.0.abs().min(dir.1.abs()) + reduce);
+            let dir = ((dir.0 * scale).clamp(-span, span), (dir.1 * scale).clamp(-span, span));
+
+            let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+            let along = |t: f32| sample(px + dir.0 * t, py + dir.1 * t);
+            let narrow = mix(along(1.0 / 3.0 - 0.5), along(2.0 / 3.0 - 0.5));
+            let wide = mix(narrow, mix(along(-0.5), along(0.5)));
+
+            let luma_wide = luma(wide);
+            dst[(y as usize) * width + x as usize] = if luma_wide < luma_min || luma_wide > luma_max {
+                narrow
+            } else {
+                wide
+            };
+        }
+    }
+}
+
+// Thresholds for ordered dithering, each cell of a 4x4 tile gets a different one
+const BAYER: [[f32; 4]; 4] = [
+    [0.0, 8.0, 2.0, 10.0],
+    [12.0, 4.0, 14.0, 6.0],
+    [3.0, 11.0, 1.0, 9.0],
+    [15.0, 7.0, 13.0, 5.0],
+];
+
+fn dither(hdr: &mut [[f32; 3]], width: usize, palette: &[[f32; 3]], spread: f32) {
+    if palette.is_empty() {
+        return;
+    }
+    for (i, pixel) in hdr.iter_mut().enumerate() {
+        let offset = ((BAYER[(i / width) % 4][i % width % 4] + 0.5) / 16.0 - 0.5) * spread;
+        let shifted = pixel.map(|c| c + offset);
+        let distance = |p: &[f32; 3]| (0..3).map(|c| (p[c] - shifted[c]).powi(2)).sum::<f32>();
+        *pixel = *palette.iter()
+            .min_by(|a, b| distance(a).total_cmp(&distance(b)))
+            .expect("palette isn't empty");
+    }
+}
+
+fn crt(
+    src: &[[f32; 3]],
+    dst: &mut [[f32; 3]],
+    width: usize,
+    height: usize,
+    curvature: f32,
+    scanlines: f32,
+    aberration: f32,
+) {
+    let (w, h) = (width as f32, height as f32);
+    for y in 0..height {
+        for x in 0..width {
+            // Position from the center, -1.0 to 1.0 across the screen
+            let u = (x as f32 + 0.5) / w * 2.0 - 1.0;
+            let v = (y as f32 + 0.5) / h * 2.0 - 1.0;
+
+            // Barrel distortion, points further out get pushed further
+            let bulge = 1.0 + curvature * (u * u + v * v);
+            let (u, v) = (u * bulge, v * bulge);
+            let out = &mut dst[y * width + x];
+            if u.abs() > 1.0 || v.abs() > 1.0 {
+                *out = [0.0; 3];
+                continue;
+            }
+
+            let sx = (u + 1.0) / 2.0 * w;
+            let sy = (v + 1.0) / 2.0 * h;
+            // Red slides outwards and blue inwards, more so near the edges
+            let (dx, dy) = (u * aberration, v * aberration);
+            let red = sample(src, width, height, sx + dx, sy + dy)[0];
+            let green = sample(src, width, height, sx, sy)[1];
+            let blue = sample(src, width, height, sx - dx, sy - dy)[2];
+
+            // Every other line of the original picture is dark, with a smooth
+            // falloff in between once the curvature bends the lines
+            let line = ((sy - 0.5) * std::f32::consts::FRAC_PI_2).cos().powi(2);
+            let shade = 1.0 - scanlines * (1.0 - line);
+            *out = [red * shade, green * shade, blue * shade];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: usize = 16;
+
+    fn run_fxaa(src: &[[f32; 3]]) -> Vec<[f32; 3]> {
+        let mut dst = vec![[0.0; 3]; src.len()];
+        fxaa(src, &mut dst, SIZE, SIZE, 8.0);
+        dst
+    }
+
+    #[test]
+    fn fxaa_leaves_flat_areas_alone() {
+        let src = vec![[0.25, 0.5, 0.75]; SIZE * SIZE];
+        for pixel in run_fxaa(&src) {
+            for (c, expected) in pixel.iter().zip([0.25, 0.5, 0.75]) {
+                assert!((c - expected).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn fxaa_smooths_stair_steps() {
+        // White below a shallow staircase that steps down every two columns
+        let solid = |x: usize, y: usize| y * 2 > x + 4;
+        let src: Vec<[f32; 3]> = (0..SIZE * SIZE)
+            .map(|i| if solid(i % SIZE, i / SIZE) { [1.0; 3] } else { [0.0; 3] })
+            .collect();
+        let dst = run_fxaa(&src);
+
+        let mut blended = 0;
+        for y in 1..SIZE - 1 {
+            for x in 1..SIZE - 1 {
+                let value = dst[y * SIZE + x][0];
+                let on_edge = (0..9).any(|i| solid(x + i % 3 - 1, y + i / 3 - 1) != solid(x, y));
+                if !on_edge {
+                    assert_eq!(value, src[y * SIZE + x][0], "({}, {}) is away from the edge", x, y);
+                } else if value > 0.01 && value < 0.99 {
+                    blended += 1;
+                }
+                assert!((0.0..=1.0).contains(&value));
+            }
+        }
+        // Most of the steps get softened
+        assert!(blended >= SIZE, "only {} edge pixels were blended", blended);
+    }
+}
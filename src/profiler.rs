@@ -0,0 +1,43 @@
+// Frame profiling with puffin. The scopes spread around the engine cost next to
+// nothing until profiling is switched on, at which point a puffin_http server
+// is started that `puffin_viewer` can connect to and show as a flamegraph.
+
+pub struct Profiler {
+    server: Option<puffin_http::Server>,
+}
+
+impl Profiler {
+
+    pub fn new() -> Self {
+        Self { server: None }
+    }
+
+    pub fn toggle(&mut self) {
+        if puffin::are_scopes_on() {
+            puffin::set_scopes_on(false);
+            return;
+        }
+
+        // The server is only started the first time profiling is enabled and
+        // then kept around so the viewer stays connected across toggles
+        if self.server.is_none() {
+            let addr = format!("127.0.0.1:{}", puffin_http::DEFAULT_PORT);
+            match puffin_http::Server::new(&addr) {
+                Ok(server) => {
+                    eprintln!("Profiler listening on {}, connect with puffin_viewer", addr);
+                    self.server = Some(server);
+                }
+                Err(err) => {
+                    eprintln!("Could not start the profiler server: {}", err);
+                    return;
+                }
+            }
+        }
+        puffin::set_scopes_on(true);
+    }
+
+    // Marks the end of a frame, call once after presenting
+    pub fn end_frame(&self) {
+        puffin::GlobalProfiler::lock().new_frame();
+    }
+}
@@ -0,0 +1,152 @@
+// Casting rays through the voxel grid. This is the DDA traversal from Amanatides
+// and Woo: rather than marching in small steps, the ray hops from one grid
+// boundary to the next so every cell it passes through is visited exactly once.
+
+pub struct RayHit {
+    // Where the ray entered the solid cell, in world pixels
+    pub position: (f32, f32),
+    pub cell: (i32, i32),
+    // Points out of the face that was hit, (0, 0) if the ray started inside
+    pub normal: (i32, i32),
+    pub distance: f32,
+    // Every cell the ray went through, in order, ending with the hit cell
+    pub cells: Vec<(i32, i32)>,
+}
+
+// `origin` and `max_dist` are in world pixels, `dir` doesn't need to be
+// normalized. Returns None if nothing solid is within `max_dist`.
+pub fn raycast(
+    is_solid: impl Fn((i32, i32)) -> bool,
+    voxel_size: f32,
+    origin: (f32, f32),
+    dir: (f32, f32),
+    max_dist: f32,
+) -> Option<RayHit> {
+    let len = (dir.0 * dir.0 + dir.1 * dir.1).sqrt();
+    if len == 0.0 {
+        return None;
+    }
+    let dir = (dir.0 / len, dir.1 / len);
+
+    // Work in cell units so cell boundaries are at whole numbers
+    let start = (origin.0 / voxel_size, origin.1 / voxel_size);
+    let mut cell = (start.0.floor() as i32, start.1.floor() as i32);
+    let step = (dir.0.signum() as i32, dir.1.signum() as i32);
+
+    // How far along the ray we have to go to cross one whole cell on each axis
+    let t_delta = (1.0 / dir.0.abs(), 1.0 / dir.1.abs());
+    // How far along the ray the next boundary on each axis is
+    let mut t_max = (
+        boundary_distance(start.0, dir.0),
+        boundary_distance(start.1, dir.1),
+    );
+
+    let max_t = max_dist / voxel_size;
+    let mut t = 0.0;
+    let mut normal = (0, 0);
+    let mut cells = Vec::new();
+
+    loop {
+        cells.push(cell);
+        if is_solid(cell) {
+            let distance = t * voxel_size;
+            return Some(RayHit {
+                position: (origin.0 + dir.0 * distance, origin.1 + dir.1 * distance),
+                cell,
+                normal,
+                distance,
+                cells,
+            });
+        }
+
+        if t_max.0 < t_max.1 {
+            t = t_max.0;
+            t_max.0 += t_delta.0;
+            cell.0 += step.0;
+            normal = (-step.0, 0);
+        } else {
+            t = t_max.1;
+            t_max.1 += t_delta.1;
+            cell.1 += step.1;
+            normal = (0, -step.1);
+        }
+
+        if t > max_t {
+            return None;
+        }
+    }
+}
+
+fn boundary_distance(pos: f32, dir: f32) -> f32 {
+    if dir > 0.0 {
+        (pos.floor() + 1.0 - pos) / dir
+    } else if dir < 0.0 {
+        (pos - pos.floor()) / -dir
+    } else {
+        f32::INFINITY
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: f32 = 10.0;
+
+    // From the middle of cell (0, 0) towards a single solid cell
+    fn cast_at(solid: (i32, i32), dir: (f32, f32), max_dist: f32) -> Option<RayHit> {
+        raycast(|cell| cell == solid, SIZE, (5.0, 5.0), dir, max_dist)
+    }
+
+    #[test]
+    fn normal_points_out_of_the_face_hit() {
+        for (solid, dir, normal, distance) in [
+            ((3, 0), (1.0, 0.0), (-1, 0), 25.0),
+            ((-2, 0), (-1.0, 0.0), (1, 0), 15.0),
+            ((0, 2), (0.0, 1.0), (0, -1), 15.0),
+            ((0, -1), (0.0, -1.0), (0, 1), 5.0),
+        ] {
+            let hit = cast_at(solid, dir, 100.0).unwrap();
+            assert_eq!(hit.cell, solid);
+            assert_eq!(hit.normal, normal);
+            assert!((hit.distance - distance).abs() < 1e-4, "{} != {}", hit.distance, distance);
+        }
+    }
+
+    #[test]
+    fn position_is_where_the_ray_enters_the_cell() {
+        let hit = cast_at((3, 0), (2.0, 0.0), 100.0).unwrap();
+        assert!((hit.position.0 - 30.0).abs() < 1e-4);
+        assert!((hit.position.1 - 5.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn cells_are_listed_in_the_order_crossed() {
+        let hit = raycast(|(x, _)| x >= 2, SIZE, (5.0, 5.0), (1.0, 0.5), 100.0).unwrap();
+        assert_eq!(hit.cells, vec![(0, 0), (1, 0), (1, 1), (2, 1)]);
+        assert_eq!(hit.cell, (2, 1));
+        assert_eq!(hit.normal, (-1, 0));
+        // 15 pixels across at a slope of 1/2
+        assert!((hit.distance - 15.0 * 1.25f32.sqrt()).abs() < 1e-3);
+    }
+
+    #[test]
+    fn stops_at_max_dist() {
+        assert!(cast_at((3, 0), (1.0, 0.0), 24.0).is_none());
+        assert!(cast_at((3, 0), (1.0, 0.0), 26.0).is_some());
+    }
+
+    #[test]
+    fn zero_length_direction_hits_nothing() {
+        assert!(raycast(|_| true, SIZE, (5.0, 5.0), (0.0, 0.0), 100.0).is_none());
+    }
+
+    #[test]
+    fn starting_inside_a_solid_cell() {
+        let hit = cast_at((0, 0), (1.0, 1.0), 100.0).unwrap();
+        assert_eq!(hit.cell, (0, 0));
+        assert_eq!(hit.normal, (0, 0));
+        assert_eq!(hit.distance, 0.0);
+        assert_eq!(hit.cells, vec![(0, 0)]);
+    }
+}
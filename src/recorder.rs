@@ -0,0 +1,114 @@
+// Records gameplay into an animated GIF. Frames are grabbed at a capped rate and
+// streamed to a worker thread which quantizes and encodes them, so recording
+// doesn't stall the frame and the file is finished shortly after stopping.
+// Only a few frames can wait for the encoder, if it falls further behind new
+// frames are dropped rather than piling up in memory.
+
+use std::error::Error;
+use std::fs::File;
+use std::io::BufWriter;
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const CAPTURE_FPS: u32 = 20;
+// Speed of the color quantizer, 1 is the best quality and 30 the fastest
+const QUANTIZE_SPEED: i32 = 10;
+// Frames that can be waiting for the encoder at once
+const QUEUE_LENGTH: usize = 4;
+
+struct Session {
+    sender: SyncSender<Vec<u8>>,
+    last_capture: Instant,
+}
+
+pub struct Recorder {
+    width: u32,
+    height: u32,
+    session: Option<Session>,
+    // Encoders that are still finishing their file after recording stopped
+    encoders: Vec<JoinHandle<()>>,
+}
+
+impl Recorder {
+
+    pub fn new(width: u32, height: u32) -> Self {
+        Self { width, height, session: None, encoders: Vec::new() }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.session.is_some()
+    }
+
+    pub fn toggle(&mut self) {
+        // Dropping the session closes the channel which lets the encoder
+        // write the end of the file and exit
+        if self.session.take().is_some() {
+            return;
+        }
+
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let path = format!("capture-{}.gif", timestamp);
+        let (sender, receiver) = mpsc::sync_channel(QUEUE_LENGTH);
+        let (width, height) = (self.width as u16, self.height as u16);
+
+        self.encoders.retain(|encoder| !encoder.is_finished());
+        self.encoders.push(thread::spawn(move || {
+            match encode(&path, width, height, receiver) {
+                Ok(frames) => eprintln!("Saved {} frames to {}", frames, path),
+                Err(err) => eprintln!("Failed to save {}: {}", path, err),
+            }
+        }));
+
+        self.session = Some(Session {
+            sender,
+            // Make sure the very first frame gets captured
+            last_capture: Instant::now() - Duration::from_secs(1),
+        });
+    }
+
+    // Call with every presented frame, only some of them end up in the file
+    pub fn capture(&mut self, frame: &[u8]) {
+        let Some(session) = &mut self.session else {
+            return;
+        };
+        let interval = Duration::from_secs(1) / CAPTURE_FPS;
+        if session.last_capture.elapsed() < interval {
+            return;
+        }
+        session.last_capture = Instant::now();
+
+        // A full queue means the encoder is behind, so skip this frame. The
+        // encoder only goes away if it failed, in which case it already
+        // reported why, so just stop recording
+        if let Err(TrySendError::Disconnected(_)) = session.sender.try_send(frame.to_vec()) {
+            self.session = None;
+        }
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        // Let any recording in progress finish writing before the engine exits
+        self.session = None;
+        for encoder in self.encoders.drain(..) {
+            let _ = encoder.join();
+        }
+    }
+}
+
+fn encode(path: &str, width: u16, height: u16, frames: Receiver<Vec<u8>>) -> Result<usize, Box<dyn Error>> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = gif::Encoder::new(file, width, height, &[])?;
+    encoder.set_repeat(gif::Repeat::Infinite)?;
+
+    let mut count = 0;
+    for mut rgba in frames {
+        let mut frame = gif::Frame::from_rgba_speed(width, height, &mut rgba, QUANTIZE_SPEED);
+        // GIF delays are in hundredths of a second
+        frame.delay = (100 / CAPTURE_FPS) as u16;
+        encoder.write_frame(&frame)?;
+        count += 1;
+    }
+    Ok(count)
+}
@@ -0,0 +1,60 @@
+// Small xorshift generator shared by everything that needs randomness: the
+// maze and dungeon generators, wave function collapse and the benchmark. It's
+// plenty for picking room sizes and maze turns, and the same seed always gives
+// the same numbers so generated worlds can be reproduced.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub struct Rng(u64);
+
+impl Rng {
+
+    pub fn new(seed: u64) -> Self {
+        // Zero would get stuck returning zero forever
+        Self(seed.max(1))
+    }
+
+    pub fn from_time() -> Self {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+        Self::new(nanos as u64)
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    // Random number in low..=high
+    pub fn range(&mut self, low: i32, high: i32) -> i32 {
+        low + (self.next() % (high - low + 1) as u64) as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_numbers() {
+        let (mut a, mut b) = (Rng::new(99), Rng::new(99));
+        for _ in 0..100 {
+            assert_eq!(a.next(), b.next());
+        }
+    }
+
+    #[test]
+    fn zero_seed_still_varies() {
+        let mut rng = Rng::new(0);
+        assert_ne!(rng.next(), rng.next());
+    }
+
+    #[test]
+    fn range_is_inclusive() {
+        let mut rng = Rng::new(1);
+        let values: Vec<i32> = (0..1000).map(|_| rng.range(-2, 2)).collect();
+        assert!(values.iter().all(|v| (-2..=2).contains(v)));
+        assert!(values.contains(&-2) && values.contains(&2));
+    }
+}
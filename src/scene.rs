@@ -0,0 +1,225 @@
+// Scenes describe a starting world for reproducible bug reports and
+// benchmarks, loaded with `--scene <file>`. They use the same `key = value`
+// lines as theme files:
+//
+//   origin = -8 -4        cell of the first character of the first row
+//   row = ....##....      one per line of the map, '#' is solid
+//   player = 0 0          cell the first player starts in
+//   hold = KeyD 60 120    hold a key from tick 60 for 120 ticks
+//   trigger = 4 -4 10 2 message Welcome in
+//                         fire an action when a player walks into the cells
+//                         from (4, -4) to (10, 2), see Action
+//
+// Scripted keys count fixed ticks, so a scene plays out the same way no
+// matter how fast frames are drawn.
+
+use std::collections::HashSet;
+use std::fs;
+
+use winit::keyboard::KeyCode;
+
+pub struct Hold {
+    key: KeyCode,
+    start: u64,
+    ticks: u64,
+}
+
+// What a trigger does when a player walks into it
+#[derive(Clone)]
+pub enum Action {
+    // Shows a line of text, in the window title since there's no text drawing
+    Message(String),
+    // Moves the player to a cell
+    Teleport((i32, i32)),
+    // Switches to the named color theme
+    Theme(String),
+}
+
+#[derive(Clone)]
+pub struct Trigger {
+    // Top left and bottom right cells, both inside the area
+    first: (i32, i32),
+    last: (i32, i32),
+    pub action: Action,
+}
+
+impl Trigger {
+
+    pub fn contains(&self, cell: (i32, i32)) -> bool {
+        (self.first.0..=self.last.0).contains(&cell.0) && (self.first.1..=self.last.1).contains(&cell.1)
+    }
+}
+
+pub struct Scene {
+    pub voxels: HashSet<(i32, i32)>,
+    pub player: (i32, i32),
+    pub triggers: Vec<Trigger>,
+    holds: Vec<Hold>,
+}
+
+impl Scene {
+
+    pub fn load(path: &str) -> Result<Scene, String> {
+        let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        Scene::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Scene, String> {
+        let mut origin = (0, 0);
+        let mut rows = Vec::new();
+        let mut player = (0, 0);
+        let mut holds = Vec::new();
+        let mut triggers = Vec::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: &str| format!("line {}: {}", number + 1, message);
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(error("expected `key = value`"));
+            };
+            let (key, value) = (key.trim(), value.trim());
+
+            match key {
+                "origin" => origin = parse_cell(value).ok_or_else(|| error("expected `x y`"))?,
+                "row" => rows.push(value.to_string()),
+                "player" => player = parse_cell(value).ok_or_else(|| error("expected `x y`"))?,
+                "hold" => holds.push(parse_hold(value).ok_or_else(|| error("expected `key start ticks`"))?),
+                "trigger" => triggers.push(parse_trigger(value).ok_or_else(|| error("expected `x0 y0 x1 y1 action ...`"))?),
+                _ => return Err(error(&format!("unknown key `{}`", key))),
+            }
+        }
+
+        let voxels = rows.iter().enumerate()
+            .flat_map(|(y, row)| row.chars().enumerate().filter(|&(_, c)| c == '#').map(move |(x, _)| (x, y)))
+            .map(|(x, y)| (origin.0 + x as i32, origin.1 + y as i32))
+            .collect();
+        Ok(Scene { voxels, player, triggers, holds })
+    }
+
+    // Whether the script holds `key` down during `tick`
+    pub fn key_held(&self, key: KeyCode, tick: u64) -> bool {
+        self.holds.iter().any(|h| h.key == key && tick >= h.start && tick < h.start + h.ticks)
+    }
+}
+
+fn parse_cell(value: &str) -> Option<(i32, i32)> {
+    let mut parts = value.split_whitespace().map(|part| part.parse().ok());
+    let cell = (parts.next()??, parts.next()??);
+    parts.next().is_none().then_some(cell)
+}
+
+fn parse_hold(value: &str) -> Option<Hold> {
+    let mut parts = value.split_whitespace();
+    let hold = Hold {
+        key: parse_key(parts.next()?)?,
+        start: parts.next()?.parse().ok()?,
+        ticks: parts.next()?.parse().ok()?,
+    };
+    parts.next().is_none().then_some(hold)
+}
+
+// `x0 y0 x1 y1` followed by `message <text>`, `teleport <x> <y>` or
+// `theme <name>`
+fn parse_trigger(value: &str) -> Option<Trigger> {
+    let mut parts = value.split_whitespace();
+    let mut number = || parts.next()?.parse::<i32>().ok();
+    let (x0, y0, x1, y1) = (number()?, number()?, number()?, number()?);
+    let kind = parts.next()?;
+    let rest = parts.collect::<Vec<_>>().join(" ");
+    let action = match kind {
+        "message" if !rest.is_empty() => Action::Message(rest),
+        "teleport" => Action::Teleport(parse_cell(&rest)?),
+        "theme" if !rest.is_empty() => Action::Theme(rest),
+        _ => return None,
+    };
+    Some(Trigger { first: (x0.min(x1), y0.min(y1)), last: (x0.max(x1), y0.max(y1)), action })
+}
+
+// Only the keys that steer players, which are all a script can drive
+fn parse_key(name: &str) -> Option<KeyCode> {
+    Some(match name {
+        "KeyW" => KeyCode::KeyW,
+        "KeyA" => KeyCode::KeyA,
+        "KeyS" => KeyCode::KeyS,
+        "KeyD" => KeyCode::KeyD,
+        "ArrowUp" => KeyCode::ArrowUp,
+        "ArrowDown" => KeyCode::ArrowDown,
+        "ArrowLeft" => KeyCode::ArrowLeft,
+        "ArrowRight" => KeyCode::ArrowRight,
+        "ShiftLeft" => KeyCode::ShiftLeft,
+        "ShiftRight" => KeyCode::ShiftRight,
+        "ControlLeft" => KeyCode::ControlLeft,
+        "ControlRight" => KeyCode::ControlRight,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_are_placed_from_the_origin() {
+        let scene = Scene::parse("origin = -2 3\nrow = #..#\nrow = .##.\nplayer = 1 4\n").unwrap();
+        let expected: HashSet<_> = [(-2, 3), (1, 3), (-1, 4), (0, 4)].into_iter().collect();
+        assert_eq!(scene.voxels, expected);
+        assert_eq!(scene.player, (1, 4));
+    }
+
+    #[test]
+    fn comments_and_blank_lines_are_skipped() {
+        let scene = Scene::parse("# a comment\n\n   \nrow = #\n").unwrap();
+        assert_eq!(scene.voxels.len(), 1);
+        assert_eq!(scene.player, (0, 0));
+    }
+
+    #[test]
+    fn holds_cover_their_window_of_ticks() {
+        let scene = Scene::parse("hold = KeyD 10 5\nhold = ShiftLeft 0 1").unwrap();
+        assert!(!scene.key_held(KeyCode::KeyD, 9));
+        assert!(scene.key_held(KeyCode::KeyD, 10));
+        assert!(scene.key_held(KeyCode::KeyD, 14));
+        assert!(!scene.key_held(KeyCode::KeyD, 15));
+        assert!(scene.key_held(KeyCode::ShiftLeft, 0));
+        assert!(!scene.key_held(KeyCode::ShiftLeft, 1));
+        assert!(!scene.key_held(KeyCode::KeyW, 12));
+    }
+
+    #[test]
+    fn bad_lines_report_their_line_number() {
+        for (text, line) in [
+            ("row = #\nthis is not a setting", "line 2:"),
+            ("\n\norigin = 1", "line 3:"),
+            ("hold = KeyQ 0 10", "line 1:"),
+            ("row = .\nplayer = 1 2 3", "line 2:"),
+            ("trigger = 0 0 1 1 explode", "line 1:"),
+            ("camera = 0 0", "line 1:"),
+        ] {
+            let err = Scene::parse(text).err().unwrap();
+            assert!(err.starts_with(line), "`{}` gave `{}`", text, err);
+        }
+    }
+
+    #[test]
+    fn trigger_corners_are_normalised() {
+        let scene = Scene::parse("trigger = 4 2 -1 -3 message  Hello   there").unwrap();
+        let trigger = &scene.triggers[0];
+        assert_eq!((trigger.first, trigger.last), ((-1, -3), (4, 2)));
+        assert!(trigger.contains((0, 0)));
+        assert!(trigger.contains((4, -3)));
+        assert!(!trigger.contains((5, 0)));
+        assert!(matches!(&trigger.action, Action::Message(text) if text == "Hello there"));
+    }
+
+    #[test]
+    fn trigger_actions() {
+        let scene = Scene::parse("trigger = 0 0 0 0 teleport -3 7\ntrigger = 0 0 0 0 theme Night Sky").unwrap();
+        assert!(matches!(scene.triggers[0].action, Action::Teleport((-3, 7))));
+        assert!(matches!(&scene.triggers[1].action, Action::Theme(name) if name == "Night Sky"));
+        assert!(Scene::parse("trigger = 0 0 0 0 teleport 1").is_err());
+        assert!(Scene::parse("trigger = 0 0 0 0 message").is_err());
+    }
+}
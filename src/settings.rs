@@ -0,0 +1,228 @@
+// Settings that survive a restart, kept in the platform config directory
+// (~/.config/rust-voxel-engine on Linux). The file uses the same `key = value`
+// lines as theme files. A missing or broken file is never fatal: anything
+// that can't be read just keeps its default.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use directories::ProjectDirs;
+
+const FILE_NAME: &str = "settings.cfg";
+pub const MIN_GRID_CELL_SIZE: f32 = 4.0;
+pub const MAX_GRID_CELL_SIZE: f32 = crate::WIDTH as f32;
+
+#[derive(Debug, PartialEq)]
+pub struct Settings {
+    // Inner size of the window in logical pixels
+    pub window_size: (u32, u32),
+    pub theme: String,
+    pub post_processing: bool,
+    pub antialiasing: bool,
+    pub crt: bool,
+    pub dithering: bool,
+    pub gamma: f32,
+    pub grid_visible: bool,
+    pub grid_cell_size: f32,
+    pub grid_subdivisions: u32,
+    pub fov_enabled: bool,
+    pub look_ahead: bool,
+}
+
+impl Settings {
+
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            window_size: (width, height),
+            theme: String::new(),
+            post_processing: true,
+            antialiasing: true,
+            crt: false,
+            dithering: false,
+            gamma: 1.0,
+            grid_visible: true,
+            grid_cell_size: 40.0,
+            grid_subdivisions: 1,
+            fov_enabled: true,
+            look_ahead: true,
+        }
+    }
+
+    // Reads the settings file, starting from the defaults for a window of
+    // `width` x `height`
+    pub fn load(width: u32, height: u32) -> Self {
+        match path() {
+            Some(path) => Self::load_from(&path, width, height),
+            None => Self::new(width, height),
+        }
+    }
+
+    fn load_from(path: &Path, width: u32, height: u32) -> Self {
+        let mut settings = Self::new(width, height);
+        let Ok(text) = fs::read_to_string(path) else {
+            return settings;
+        };
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let applied = line.split_once('=')
+                .is_some_and(|(key, value)| settings.set(key.trim(), value.trim()));
+            if !applied {
+                eprintln!("Ignoring settings line {}: `{}`", number + 1, line);
+            }
+        }
+        settings
+    }
+
+    // Applies one `key = value` pair, returns false if it isn't understood
+    fn set(&mut self, key: &str, value: &str) -> bool {
+        match key {
+            "window_width" => value.parse().map(|w| self.window_size.0 = w).is_ok(),
+            "window_height" => value.parse().map(|h| self.window_size.1 = h).is_ok(),
+            "theme" => {
+                self.theme = value.to_string();
+                true
+            }
+            "post_processing" => value.parse().map(|b| self.post_processing = b).is_ok(),
+            "antialiasing" => value.parse().map(|b| self.antialiasing = b).is_ok(),
+            "crt" => value.parse().map(|b| self.crt = b).is_ok(),
+            "dithering" => value.parse().map(|b| self.dithering = b).is_ok(),
+            "gamma" => value.parse().ok().filter(|g: &f32| g.is_finite()).map(|g| self.gamma = g).is_some(),
+            "grid_visible" => value.parse().map(|b| self.grid_visible = b).is_ok(),
+            // Same bounds as the `[` and `]` keys
+            "grid_cell_size" => value.parse().ok()
+                .filter(|s: &f32| (MIN_GRID_CELL_SIZE..=MAX_GRID_CELL_SIZE).contains(s))
+                .map(|s| self.grid_cell_size = s)
+                .is_some(),
+            "grid_subdivisions" => value.parse().ok()
+                .filter(|n| [1, 2, 4].contains(n))
+                .map(|n| self.grid_subdivisions = n)
+                .is_some(),
+            "fov_enabled" => value.parse().map(|b| self.fov_enabled = b).is_ok(),
+            "look_ahead" => value.parse().map(|b| self.look_ahead = b).is_ok(),
+            _ => false,
+        }
+    }
+
+    pub fn save(&self) {
+        if let Some(path) = path() {
+            self.save_to(&path);
+        }
+    }
+
+    fn save_to(&self, path: &Path) {
+        let text = format!(
+            "window_width = {}\n\
+             window_height = {}\n\
+             theme = {}\n\
+             post_processing = {}\n\
+             antialiasing = {}\n\
+             crt = {}\n\
+             dithering = {}\n\
+             gamma = {}\n\
+             grid_visible = {}\n\
+             grid_cell_size = {}\n\
+             grid_subdivisions = {}\n\
+             fov_enabled = {}\n\
+             look_ahead = {}\n",
+            self.window_size.0, self.window_size.1, self.theme, self.post_processing,
+            self.antialiasing, self.crt, self.dithering, self.gamma, self.grid_visible,
+            self.grid_cell_size, self.grid_subdivisions, self.fov_enabled, self.look_ahead,
+        );
+        let result = path.parent().map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(path, text));
+        if let Err(err) = result {
+            eprintln!("Couldn't save settings to {}: {}", path.display(), err);
+        }
+    }
+}
+
+fn path() -> Option<PathBuf> {
+    ProjectDirs::from("", "", "rust-voxel-engine").map(|dirs| dirs.config_dir().join(FILE_NAME))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_values_that_arent_numbers() {
+        let mut settings = Settings::new(640, 480);
+        for value in ["NaN", "inf", "-inf", "bright"] {
+            assert!(!settings.set("gamma", value), "accepted gamma {}", value);
+        }
+        assert!(!settings.set("grid_cell_size", "NaN"));
+        assert_eq!(settings, Settings::new(640, 480));
+    }
+
+    #[test]
+    fn rejects_out_of_range_values() {
+        let mut settings = Settings::new(640, 480);
+        assert!(!settings.set("grid_cell_size", "2"));
+        assert!(!settings.set("grid_cell_size", "100000"));
+        assert!(!settings.set("grid_subdivisions", "3"));
+        assert!(!settings.set("grid_subdivisions", "0"));
+        assert!(!settings.set("window_width", "-640"));
+        assert_eq!(settings, Settings::new(640, 480));
+
+        assert!(settings.set("grid_cell_size", &MIN_GRID_CELL_SIZE.to_string()));
+        assert!(settings.set("grid_subdivisions", "4"));
+        assert_eq!(settings.grid_cell_size, MIN_GRID_CELL_SIZE);
+        assert_eq!(settings.grid_subdivisions, 4);
+    }
+
+    #[test]
+    fn rejects_bad_booleans_and_unknown_keys() {
+        let mut settings = Settings::new(640, 480);
+        for value in ["yes", "1", "True", ""] {
+            assert!(!settings.set("crt", value), "accepted crt {:?}", value);
+        }
+        assert!(!settings.set("volume", "11"));
+        assert_eq!(settings, Settings::new(640, 480));
+    }
+
+    #[test]
+    fn broken_lines_keep_their_defaults() {
+        let path = std::env::temp_dir().join(format!("voxel-settings-broken-{}.cfg", std::process::id()));
+        fs::write(&path, "# comment\ngamma = NaN\ncrt = true\nno equals sign\nvolume = 11\n").unwrap();
+        let settings = Settings::load_from(&path, 640, 480);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(settings, Settings { crt: true, ..Settings::new(640, 480) });
+    }
+
+    #[test]
+    fn saved_settings_load_back() {
+        let path = std::env::temp_dir()
+            .join(format!("voxel-settings-{}", std::process::id()))
+            .join(FILE_NAME);
+        let settings = Settings {
+            window_size: (1280, 720),
+            theme: "Solarized".to_string(),
+            post_processing: false,
+            antialiasing: false,
+            crt: true,
+            dithering: true,
+            gamma: 1.3,
+            grid_visible: false,
+            grid_cell_size: 16.0,
+            grid_subdivisions: 2,
+            fov_enabled: false,
+            look_ahead: false,
+        };
+        settings.save_to(&path);
+        let loaded = Settings::load_from(&path, 640, 480);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+
+        assert_eq!(loaded, settings);
+    }
+
+    #[test]
+    fn missing_file_gives_the_defaults() {
+        let path = std::env::temp_dir().join("voxel-settings-that-does-not-exist.cfg");
+        assert_eq!(Settings::load_from(&path, 800, 600), Settings::new(800, 600));
+    }
+}
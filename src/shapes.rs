@@ -0,0 +1,109 @@
+// Shapes for building by hand between two anchor cells: straight walls,
+// rectangles and circles, hollow or filled. Each one comes back as the list
+// of cells it covers, which get added to the world without clearing anything
+// around them.
+
+// Wall along the cells a straight line from `from` to `to` passes over
+pub fn line(from: (i32, i32), to: (i32, i32)) -> Vec<(i32, i32)> {
+    let (dx, dy) = ((to.0 - from.0).abs(), -(to.1 - from.1).abs());
+    let step = ((to.0 - from.0).signum(), (to.1 - from.1).signum());
+
+    // Bresenham's line, stepping along whichever axis the error says is
+    // further behind
+    let mut cells = Vec::new();
+    let (mut x, mut y) = from;
+    let mut error = dx + dy;
+    loop {
+        cells.push((x, y));
+        if (x, y) == to {
+            return cells;
+        }
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            x += step.0;
+        }
+        if doubled <= dx {
+            error += dx;
+            y += step.1;
+        }
+    }
+}
+
+// Rectangle with `from` and `to` as opposite corners, just the walls unless
+// `filled`
+pub fn rectangle(from: (i32, i32), to: (i32, i32), filled: bool) -> Vec<(i32, i32)> {
+    let (left, right) = (from.0.min(to.0), from.0.max(to.0));
+    let (top, bottom) = (from.1.min(to.1), from.1.max(to.1));
+    let mut cells = Vec::new();
+    for y in top..=bottom {
+        for x in left..=right {
+            let edge = x == left || x == right || y == top || y == bottom;
+            if filled || edge {
+                cells.push((x, y));
+            }
+        }
+    }
+    cells
+}
+
+// Circle around `center` reaching out to `edge`, just the ring unless `filled`
+pub fn circle(center: (i32, i32), edge: (i32, i32), filled: bool) -> Vec<(i32, i32)> {
+    let radius = ((edge.0 - center.0) as f32).hypot((edge.1 - center.1) as f32).round() as i32;
+    // Going a little past r² rounds the outline off instead of leaving a
+    // single cell poking out on each axis
+    let inside = |x: i32, y: i32| x * x + y * y <= radius * radius + radius;
+
+    let mut cells = Vec::new();
+    for y in -radius..=radius {
+        for x in -radius..=radius {
+            // Ring cells are the ones next to a cell outside the circle
+            let ring = !(inside(x - 1, y) && inside(x + 1, y) && inside(x, y - 1) && inside(x, y + 1));
+            if inside(x, y) && (filled || ring) {
+                cells.push((center.0 + x, center.1 + y));
+            }
+        }
+    }
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_join_their_ends_without_gaps() {
+        for to in [(5, 2), (-4, 7), (0, -3), (-6, -6), (3, 0), (1, 1)] {
+            let cells = line((1, 1), to);
+            assert_eq!(cells.first(), Some(&(1, 1)));
+            assert_eq!(cells.last(), Some(&to));
+            // One cell per step along the longer axis, each touching the last
+            assert_eq!(cells.len() as i32, (to.0 - 1).abs().max((to.1 - 1).abs()) + 1);
+            for pair in cells.windows(2) {
+                assert!((pair[0].0 - pair[1].0).abs() <= 1 && (pair[0].1 - pair[1].1).abs() <= 1);
+            }
+        }
+    }
+
+    #[test]
+    fn rectangles_are_hollow_or_filled() {
+        let hollow = rectangle((3, 4), (0, 0), false);
+        assert_eq!(hollow.len(), 14);
+        assert!(hollow.contains(&(0, 0)) && hollow.contains(&(3, 4)));
+        assert!(!hollow.contains(&(1, 2)));
+        assert_eq!(rectangle((3, 4), (0, 0), true).len(), 20);
+    }
+
+    #[test]
+    fn circles_reach_the_edge_cell() {
+        let ring = circle((10, 10), (10, 15), false);
+        assert!(ring.contains(&(10, 15)) && ring.contains(&(5, 10)));
+        assert!(!ring.contains(&(10, 10)));
+        assert!(ring.iter().all(|&(x, y)| ((x - 10) as f32).hypot((y - 10) as f32) < 5.5));
+
+        let disc = circle((10, 10), (10, 15), true);
+        assert!(disc.contains(&(10, 10)));
+        assert!(ring.iter().all(|cell| disc.contains(cell)));
+        assert_eq!(circle((0, 0), (0, 0), false), vec![(0, 0)]);
+    }
+}
@@ -0,0 +1,251 @@
+// Color themes. A theme is a small text file with one `key = value` pair per
+// line where the values are #rrggbb colors, or a list of them for the optional
+// palette. The themes that ship with the engine are compiled in, and any
+// `.theme` files in a `themes` folder next to where the engine is started from
+// get added to (or replace) them.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+const BUILT_IN: [&str; 3] = [
+    include_str!("../themes/default.theme"),
+    include_str!("../themes/deuteranopia.theme"),
+    include_str!("../themes/protanopia.theme"),
+];
+
+const USER_DIR: &str = "themes";
+
+#[derive(Clone)]
+pub struct Theme {
+    pub name: String,
+    pub background: [u8; 4],
+    pub grid_major: [u8; 4],
+    pub grid_minor: [u8; 4],
+    pub voxel: [u8; 4],
+    pub ray: [u8; 4],
+    pub ray_hit: [u8; 4],
+    pub players: [[u8; 4]; 2],
+    pub bird: [u8; 4],
+    pub divider: [u8; 4],
+    // Colors the retro dither pass quantizes to, empty means the theme's own
+    pub palette: Vec<[u8; 4]>,
+}
+
+impl Theme {
+
+    pub fn parse(text: &str) -> Result<Theme, String> {
+        const KEYS: [&str; 10] = [
+            "background", "grid_major", "grid_minor", "voxel", "ray",
+            "ray_hit", "player_one", "player_two", "bird", "divider",
+        ];
+
+        let mut name = None;
+        let mut colors = HashMap::new();
+        let mut palette = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(format!("line {}: expected `key = value`", number + 1));
+            };
+            let (key, value) = (key.trim(), value.trim());
+
+            if key == "name" {
+                name = Some(value.to_string());
+            } else if key == "palette" {
+                palette = value.split_whitespace()
+                    .map(|v| parse_color(v).ok_or_else(|| format!("line {}: `{}` is not a #rrggbb color", number + 1, v)))
+                    .collect::<Result<_, _>>()?;
+            } else if KEYS.contains(&key) {
+                let color = parse_color(value)
+                    .ok_or_else(|| format!("line {}: `{}` is not a #rrggbb color", number + 1, value))?;
+                colors.insert(key, color);
+            } else {
+                return Err(format!("line {}: unknown key `{}`", number + 1, key));
+            }
+        }
+
+        let color = |key: &str| colors.get(key).copied().ok_or_else(|| format!("missing `{}`", key));
+        Ok(Theme {
+            name: name.ok_or("missing `name`")?,
+            background: color("background")?,
+            grid_major: color("grid_major")?,
+            grid_minor: color("grid_minor")?,
+            voxel: color("voxel")?,
+            ray: color("ray")?,
+            ray_hit: color("ray_hit")?,
+            players: [color("player_one")?, color("player_two")?],
+            bird: color("bird")?,
+            divider: color("divider")?,
+            palette,
+        })
+    }
+
+    // The palette to dither with, falls back to the colors the theme draws with
+    pub fn dither_palette(&self) -> Vec<[u8; 4]> {
+        if !self.palette.is_empty() {
+            return self.palette.clone();
+        }
+        let mut colors = vec![
+            self.background, self.grid_major, self.grid_minor, self.voxel, self.ray,
+            self.ray_hit, self.players[0], self.players[1], self.bird, self.divider,
+        ];
+        colors.sort();
+        colors.dedup();
+        colors
+    }
+}
+
+fn parse_color(value: &str) -> Option<[u8; 4]> {
+    let hex = value.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?, 0xff])
+}
+
+pub struct Themes {
+    list: Vec<Theme>,
+    current: usize,
+}
+
+impl Themes {
+
+    pub fn load() -> Self {
+        let mut themes = Self::built_in();
+        for theme in load_dir(Path::new(USER_DIR)) {
+            match themes.list.iter_mut().find(|t| t.name == theme.name) {
+                Some(existing) => *existing = theme,
+                None => themes.list.push(theme),
+            }
+        }
+        themes
+    }
+
+    // Just the themes compiled into the engine, without the user directory
+    pub fn built_in() -> Self {
+        let list = BUILT_IN.iter()
+            .map(|text| Theme::parse(text).expect("built-in themes are valid"))
+            .collect();
+        Self { list, current: 0 }
+    }
+
+    pub fn current(&self) -> &Theme {
+        &self.list[self.current]
+    }
+
+    // Switches to the theme called `name`, returns false if there is none
+    pub fn select(&mut self, name: &str) -> bool {
+        match self.list.iter().position(|t| t.name == name) {
+            Some(index) => {
+                self.current = index;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Switches to the next theme and returns it
+    pub fn cycle(&mut self) -> &Theme {
+        self.current = (self.current + 1) % self.list.len();
+        self.current()
+    }
+}
+
+// Reads every .theme file in `dir`, a missing folder just means no themes
+fn load_dir(dir: &Path) -> Vec<Theme> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "theme"))
+        .collect();
+    paths.sort();
+
+    paths.into_iter().filter_map(|path| {
+        let result = fs::read_to_string(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|text| Theme::parse(&text));
+        match result {
+            Ok(theme) => Some(theme),
+            Err(err) => {
+                eprintln!("Skipping theme {}: {}", path.display(), err);
+                None
+            }
+        }
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALID: &str = "\
+        # A comment\n\
+        name = test\n\
+        background = #000000\n\
+        grid_major = #0000ff\n\
+        grid_minor = #000060\n\
+        voxel = #505050\n\
+        \n\
+        ray = #00ff00\n\
+        ray_hit = #FFFF00\n\
+        player_one = #ff0000\n\
+        player_two = #ffa000\n\
+        bird = #e0e0e0\n\
+        divider = #808080\n";
+
+    #[test]
+    fn parses_a_valid_theme() {
+        let theme = Theme::parse(VALID).unwrap();
+        assert_eq!(theme.name, "test");
+        assert_eq!(theme.voxel, [0x50, 0x50, 0x50, 0xff]);
+        assert_eq!(theme.ray_hit, [0xff, 0xff, 0x00, 0xff]);
+        assert_eq!(theme.players, [[0xff, 0x00, 0x00, 0xff], [0xff, 0xa0, 0x00, 0xff]]);
+        // Without a palette the theme dithers to its own colors
+        assert!(theme.palette.is_empty());
+        assert_eq!(theme.dither_palette().len(), 10);
+
+        let theme = Theme::parse(&format!("{}palette = #000000 #ffffff\n", VALID)).unwrap();
+        assert_eq!(theme.dither_palette(), [[0, 0, 0, 0xff], [0xff, 0xff, 0xff, 0xff]]);
+    }
+
+    #[test]
+    fn missing_keys_are_errors() {
+        let without = |key: &str| {
+            VALID.lines().filter(|line| !line.starts_with(key)).collect::<Vec<_>>().join("\n")
+        };
+        assert_eq!(Theme::parse(&without("bird")).err().unwrap(), "missing `bird`");
+        assert_eq!(Theme::parse(&without("name")).err().unwrap(), "missing `name`");
+    }
+
+    #[test]
+    fn malformed_lines_are_errors() {
+        let broken = [
+            "voxel = 505050",
+            "voxel = #50505",
+            "voxel = #5050500",
+            "voxel = #gg5050",
+            "voxel = #ü0000",
+            "voxel = ",
+            "palette = #000000 red",
+            "voxel #505050",
+            "sky = #000000",
+        ];
+        for line in broken {
+            let text = format!("{}{}\n", VALID, line);
+            assert!(Theme::parse(&text).is_err(), "accepted `{}`", line);
+        }
+    }
+
+    #[test]
+    fn built_in_themes_parse() {
+        assert_eq!(Themes::built_in().list.len(), BUILT_IN.len());
+    }
+}
@@ -0,0 +1,251 @@
+// Wave Function Collapse, the overlapping model. Every NxN patch of an example
+// template becomes a pattern, and two patterns are allowed next to each other
+// if they agree where they overlap. The output starts with every pattern
+// possible everywhere. Then the cell with the fewest options is collapsed to
+// one pattern and the consequences are propagated to its neighbours, over and
+// over until every cell is decided (or we hit a contradiction and retry).
+
+use std::collections::HashMap;
+
+use crate::generate::Layout;
+use crate::rng::Rng;
+
+// Walled rooms with doorways, '#' is solid
+pub const RUINS: [&str; 14] = [
+    "####################",
+    "#.....#......#.....#",
+    "#.....#......#.....#",
+    "#............#.....#",
+    "#.....#......#.....#",
+    "###.###......###.###",
+    "......#......#......",
+    "......###.####......",
+    "....................",
+    "..####.....#####.##.",
+    "..#...........#...#.",
+    "..#...#.......#...#.",
+    "..#####.......#####.",
+    "....................",
+];
+
+const ATTEMPTS: usize = 10;
+
+// Right, left, down, up
+const DIRECTIONS: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+
+fn opposite(d: usize) -> usize {
+    d ^ 1
+}
+
+struct Patterns {
+    cells: Vec<Vec<bool>>,
+    weights: Vec<f32>,
+    // For a pattern and direction, the patterns that may sit next to it there
+    propagator: [Vec<Vec<usize>>; 4],
+}
+
+// Generates a `width` x `height` layout in the style of `template`, or None
+// if every attempt ran into a contradiction
+pub fn synthesize(template: &[&str], n: usize, width: i32, height: i32, rng: &mut Rng) -> Option<Layout> {
+    let patterns = learn(template, n);
+    (0..ATTEMPTS).find_map(|_| run(&patterns, width as usize, height as usize, rng))
+        .map(|solid| Layout::new(width, height, solid))
+}
+
+fn learn(template: &[&str], n: usize) -> Patterns {
+    let grid: Vec<Vec<bool>> = template.iter().map(|row| row.chars().map(|c| c == '#').collect()).collect();
+    let th = grid.len();
+    let tw = grid[0].len();
+
+    // Count every patch, together with its rotations and mirror images so the
+    // output isn't stuck with the orientation of the template
+    let mut counts: HashMap<Vec<bool>, usize> = HashMap::new();
+    let mut order = Vec::new();
+    for y in 0..=th - n {
+        for x in 0..=tw - n {
+            let mut patch: Vec<bool> = (0..n * n).map(|i| grid[y + i / n][x + i % n]).collect();
+            for _ in 0..4 {
+                for variant in [patch.clone(), mirror(&patch, n)] {
+                    let count = counts.entry(variant.clone()).or_insert(0);
+                    if *count == 0 {
+                        order.push(variant);
+                    }
+                    *count += 1;
+                }
+                patch = rotate(&patch, n);
+            }
+        }
+    }
+
+    let weights = order.iter().map(|p| counts[p] as f32).collect();
+    let propagator = std::array::from_fn(|d| {
+        order.iter()
+            .map(|a| (0..order.len()).filter(|&b| agrees(a, &order[b], n, DIRECTIONS[d])).collect())
+            .collect()
+    });
+    Patterns { cells: order, weights, propagator }
+}
+
+fn rotate(p: &[bool], n: usize) -> Vec<bool> {
+    (0..n * n).map(|i| p[(n - 1 - i % n) * n + i / n]).collect()
+}
+
+fn mirror(p: &[bool], n: usize) -> Vec<bool> {
+    (0..n * n).map(|i| p[(i / n) * n + n - 1 - i % n]).collect()
+}
+
+// Whether `b` shifted by `offset` relative to `a` matches it where they overlap
+fn agrees(a: &[bool], b: &[bool], n: usize, offset: (i32, i32)) -> bool {
+    let n = n as i32;
+    for y in 0.max(offset.1)..n.min(n + offset.1) {
+        for x in 0.max(offset.0)..n.min(n + offset.0) {
+            let ai = (y * n + x) as usize;
+            let bi = ((y - offset.1) * n + x - offset.0) as usize;
+            if a[ai] != b[bi] {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+struct Wave {
+    width: usize,
+    height: usize,
+    possible: Vec<Vec<bool>>,
+    remaining: Vec<usize>,
+    // How many patterns in the neighbour in each direction still allow this
+    // pattern, once it drops to zero the pattern is banned
+    support: Vec<Vec<[usize; 4]>>,
+    pending: Vec<(usize, usize)>,
+}
+
+impl Wave {
+
+    fn new(patterns: &Patterns, width: usize, height: usize) -> Self {
+        let count = patterns.cells.len();
+        let support: Vec<[usize; 4]> = (0..count)
+            .map(|t| std::array::from_fn(|d| patterns.propagator[opposite(d)][t].len()))
+            .collect();
+        Self {
+            width,
+            height,
+            possible: vec![vec![true; count]; width * height],
+            remaining: vec![count; width * height],
+            support: vec![support; width * height],
+            pending: Vec::new(),
+        }
+    }
+
+    fn neighbour(&self, i: usize, d: usize) -> Option<usize> {
+        let x = (i % self.width) as i32 + DIRECTIONS[d].0;
+        let y = (i / self.width) as i32 + DIRECTIONS[d].1;
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            None
+        } else {
+            Some(y as usize * self.width + x as usize)
+        }
+    }
+
+    fn ban(&mut self, i: usize, t: usize) {
+        if self.possible[i][t] {
+            self.possible[i][t] = false;
+            self.remaining[i] -= 1;
+            self.pending.push((i, t));
+        }
+    }
+
+    // Returns false on a contradiction, a cell with no patterns left
+    fn propagate(&mut self, patterns: &Patterns) -> bool {
+        while let Some((i, t)) = self.pending.pop() {
+            if self.remaining[i] == 0 {
+                return false;
+            }
+            for d in 0..4 {
+                let Some(j) = self.neighbour(i, d) else {
+                    continue;
+                };
+                for &other in &patterns.propagator[d][t] {
+                    let support = &mut self.support[j][other][d];
+                    *support -= 1;
+                    if *support == 0 {
+                        self.ban(j, other);
+                    }
+                }
+            }
+        }
+        true
+    }
+}
+
+fn run(patterns: &Patterns, width: usize, height: usize, rng: &mut Rng) -> Option<Vec<bool>> {
+    let mut wave = Wave::new(patterns, width, height);
+
+    loop {
+        // The undecided cell with the fewest options left, ties broken randomly
+        let next = (0..width * height)
+            .filter(|&i| wave.remaining[i] > 1)
+            .min_by_key(|&i| (wave.remaining[i], rng.next() % 1024));
+        let Some(i) = next else {
+            break;
+        };
+
+        // Pick one of its patterns, weighted by how often it was seen
+        let options: Vec<usize> = (0..patterns.cells.len()).filter(|&t| wave.possible[i][t]).collect();
+        let total: f32 = options.iter().map(|&t| patterns.weights[t]).sum();
+        let mut roll = (rng.next() % 1_000_000) as f32 / 1_000_000.0 * total;
+        let mut chosen = options[options.len() - 1];
+        for &t in &options {
+            roll -= patterns.weights[t];
+            if roll <= 0.0 {
+                chosen = t;
+                break;
+            }
+        }
+
+        for &t in &options {
+            if t != chosen {
+                wave.ban(i, t);
+            }
+        }
+        if !wave.propagate(patterns) {
+            return None;
+        }
+    }
+
+    // Every cell takes the top left value of the pattern it ended up with
+    (0..width * height)
+        .map(|i| wave.possible[i].iter().position(|&p| p).map(|t| patterns.cells[t][0]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_only_contains_patches_from_the_template() {
+        let n = 3;
+        let patterns = learn(&RUINS, n);
+        let (width, height) = (32, 24);
+        let layout = synthesize(&RUINS, n, width, height, &mut Rng::new(7)).unwrap();
+
+        for y in 0..=height - n as i32 {
+            for x in 0..=width - n as i32 {
+                let patch: Vec<bool> = (0..n * n)
+                    .map(|i| layout.is_solid(x + (i % n) as i32, y + (i / n) as i32))
+                    .collect();
+                assert!(patterns.cells.contains(&patch), "patch at ({}, {}) isn't in the template", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_layout() {
+        let a = synthesize(&RUINS, 3, 20, 20, &mut Rng::new(42)).unwrap();
+        let b = synthesize(&RUINS, 3, 20, 20, &mut Rng::new(42)).unwrap();
+        for (x, y) in (0..20).flat_map(|y| (0..20).map(move |x| (x, y))) {
+            assert_eq!(a.is_solid(x, y), b.is_solid(x, y));
+        }
+    }
+}